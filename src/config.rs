@@ -1,10 +1,14 @@
-use crate::error::Error;
-use crate::error::Result;
+#[cfg(not(test))]
+use crate::error::{Error, Result};
+#[cfg(not(test))]
 use dotenvy::dotenv;
-use std::env;
-use std::str::FromStr;
+#[cfg(not(test))]
+use std::{env, str::FromStr};
 use std::sync::OnceLock;
 
+const DEFAULT_USER_AGENT: &str = concat!("astrafoto-bot/", env!("CARGO_PKG_VERSION"));
+
+#[cfg(not(test))]
 pub fn config() -> &'static Config {
     static INSTANCE: OnceLock<Config> = OnceLock::new();
 
@@ -15,44 +19,251 @@ pub fn config() -> &'static Config {
     })
 }
 
+#[cfg(test)]
+pub fn config() -> &'static Config {
+    static INSTANCE: OnceLock<Config> = OnceLock::new();
+
+    INSTANCE.get_or_init(Config::for_tests)
+}
+
 #[allow(dead_code)]
 #[allow(non_snake_case)]
 pub struct Config {
+    pub RUST_LOG: String,
+    pub LOG_FORMAT: String,
+    pub LOG_REDACT: bool,
+    pub MESSENGER: String,
+    pub CHANNELS: String,
     pub API_URL: String,
     pub ID_INSTANCE: String,
     pub API_TOKEN_INSTANCE: String,
     pub ADMIN_CHAT_ID: String,
+    pub ORDER_SINK: String,
     pub WORKER_URL: String,
+    pub WORKER_API_VERSION: u8,
+    pub WORKER_AUTH: String,
+    pub WORKER_SECRET: String,
+    pub HOT_FOLDER_DIR: String,
+    pub HOT_FOLDER_STAGING_DIR: String,
+    pub HOT_FOLDER_MANIFEST: String,
+    pub ORDER_EMAIL_TO: String,
+    pub TELEGRAM_API_URL: String,
+    pub TELEGRAM_BOT_TOKEN: String,
+    pub QUARANTINE_DIR: String,
+    pub AUDIT_LOG_FILE: String,
+    pub REPORT_TIME: String,
+    pub HEALTH_CHECK_INTERVAL: u64,
+    pub ALERT_CHANNEL: String,
+    pub ALERT_LOG_FILE: String,
+    pub EMAIL_FROM: String,
+    pub ALERT_EMAIL_TO: String,
+    pub SMTP_HOST: String,
+    pub SMTP_PORT: u16,
+    pub SMTP_USER: String,
+    pub SMTP_PASSWORD: String,
     pub SHOP_ADDRESS: String,
     pub SHOP_PHONE: String,
+    pub FAQ_FILE: String,
+    pub SHOP_TIMEZONE: String,
+    pub SHOP_HOURS: String,
+    pub SHOP_HOLIDAYS: String,
+    pub PRODUCTION_MINUTES: i64,
+    pub PRODUCTION_CAPACITY: String,
+    pub PRODUCTION_DEFAULT_RATE: u32,
+    pub OFF_HOURS_NOTICE: bool,
+    pub HOLD_OFF_HOURS: bool,
     pub NO_FILES_TIMEOUT: u64,
     pub REPEAT_COUNT: i32,
     pub REPEAT_TIMEOUT: u64,
+    pub OUTBOX_GLOBAL_INTERVAL_MS: u64,
+    pub OUTBOX_CHAT_INTERVAL_MS: u64,
+    pub OUTBOX_RETRY_MS: u64,
+    pub OUTBOX_MAX_RETRIES: u32,
+    pub HTTP_CONNECT_TIMEOUT: u64,
+    pub HTTP_TIMEOUT: u64,
+    pub HTTP_PROXY: String,
+    pub HTTP_USER_AGENT: String,
+    pub HTTP_LISTEN_ADDR: String,
+    pub FILES_PUBLIC_URL: String,
+    pub METRICS_ENABLED: bool,
+    pub PAYMENT_ENABLED: bool,
+    pub PAYMENT_API_URL: String,
+    pub PAYMENT_API_TOKEN: String,
+    pub PAYMENT_TIMEOUT: u64,
 }
 
 impl Config {
+    #[cfg(not(test))]
     fn load_from_env() -> Result<Config> {
         dotenv().expect("dotenv init failed");
         Ok(Config {
-            API_URL: get_env("API_URL")?,
-            ID_INSTANCE: get_env("ID_INSTANCE")?,
-            API_TOKEN_INSTANCE: get_env("API_TOKEN_INSTANCE")?,
+            RUST_LOG: get_env_or("RUST_LOG", "info"),
+            LOG_FORMAT: get_env_or("LOG_FORMAT", "text"),
+            LOG_REDACT: get_env_as_parse_or("LOG_REDACT", true)?,
+            MESSENGER: get_env_or("MESSENGER", "whatsapp"),
+            CHANNELS: get_env_or("CHANNELS", ""),
+            API_URL: get_env_or("API_URL", ""),
+            ID_INSTANCE: get_env_or("ID_INSTANCE", ""),
+            API_TOKEN_INSTANCE: get_env_or("API_TOKEN_INSTANCE", ""),
             ADMIN_CHAT_ID: get_env("ADMIN_CHAT_ID")?,
-            WORKER_URL: get_env("WORKER_URL")?,
+            ORDER_SINK: get_env_or("ORDER_SINK", "worker"),
+            WORKER_URL: get_env_or("WORKER_URL", ""),
+            WORKER_API_VERSION: get_env_as_parse_or("WORKER_API_VERSION", 1)?,
+            WORKER_AUTH: get_env_or("WORKER_AUTH", "none"),
+            WORKER_SECRET: get_env_or("WORKER_SECRET", ""),
+            HOT_FOLDER_DIR: get_env_or("HOT_FOLDER_DIR", "hotfolder"),
+            HOT_FOLDER_STAGING_DIR: get_env_or("HOT_FOLDER_STAGING_DIR", "hotfolder-staging"),
+            HOT_FOLDER_MANIFEST: get_env_or("HOT_FOLDER_MANIFEST", "json"),
+            ORDER_EMAIL_TO: get_env_or("ORDER_EMAIL_TO", ""),
+            TELEGRAM_API_URL: get_env_or("TELEGRAM_API_URL", "https://api.telegram.org"),
+            TELEGRAM_BOT_TOKEN: get_env_or("TELEGRAM_BOT_TOKEN", ""),
+            QUARANTINE_DIR: get_env_or("QUARANTINE_DIR", "quarantine"),
+            AUDIT_LOG_FILE: get_env_or("AUDIT_LOG_FILE", "audit.jsonl"),
+            REPORT_TIME: get_env_or("REPORT_TIME", ""),
+            HEALTH_CHECK_INTERVAL: get_env_as_parse_or("HEALTH_CHECK_INTERVAL", 60)?,
+            ALERT_CHANNEL: get_env_or("ALERT_CHANNEL", "log"),
+            ALERT_LOG_FILE: get_env_or("ALERT_LOG_FILE", "alerts.log"),
+            EMAIL_FROM: get_env_or("EMAIL_FROM", ""),
+            ALERT_EMAIL_TO: get_env_or("ALERT_EMAIL_TO", ""),
+            SMTP_HOST: get_env_or("SMTP_HOST", ""),
+            SMTP_PORT: get_env_as_parse_or("SMTP_PORT", 587)?,
+            SMTP_USER: get_env_or("SMTP_USER", ""),
+            SMTP_PASSWORD: get_env_or("SMTP_PASSWORD", ""),
             SHOP_ADDRESS: get_env("SHOP_ADDRESS")?,
             SHOP_PHONE: get_env("SHOP_PHONE")?,
+            FAQ_FILE: get_env_or("FAQ_FILE", "faq.json"),
+            SHOP_TIMEZONE: get_env_or("SHOP_TIMEZONE", "UTC"),
+            SHOP_HOURS: get_env_or("SHOP_HOURS", "00:00-24:00"),
+            SHOP_HOLIDAYS: get_env_or("SHOP_HOLIDAYS", ""),
+            PRODUCTION_MINUTES: get_env_as_parse_or("PRODUCTION_MINUTES", 60)?,
+            PRODUCTION_CAPACITY: get_env_or("PRODUCTION_CAPACITY", ""),
+            PRODUCTION_DEFAULT_RATE: get_env_as_parse_or("PRODUCTION_DEFAULT_RATE", 100)?,
+            OFF_HOURS_NOTICE: get_env_as_parse_or("OFF_HOURS_NOTICE", false)?,
+            HOLD_OFF_HOURS: get_env_as_parse_or("HOLD_OFF_HOURS", false)?,
             NO_FILES_TIMEOUT: get_env_as_parse("NO_FILES_TIMEOUT")?,
             REPEAT_COUNT: get_env_as_parse("REPEAT_COUNT")?,
             REPEAT_TIMEOUT: get_env_as_parse("REPEAT_TIMEOUT")?,
+            OUTBOX_GLOBAL_INTERVAL_MS: get_env_as_parse_or("OUTBOX_GLOBAL_INTERVAL_MS", 1000)?,
+            OUTBOX_CHAT_INTERVAL_MS: get_env_as_parse_or("OUTBOX_CHAT_INTERVAL_MS", 2000)?,
+            OUTBOX_RETRY_MS: get_env_as_parse_or("OUTBOX_RETRY_MS", 2000)?,
+            OUTBOX_MAX_RETRIES: get_env_as_parse_or("OUTBOX_MAX_RETRIES", 5)?,
+            HTTP_CONNECT_TIMEOUT: get_env_as_parse_or("HTTP_CONNECT_TIMEOUT", 5)?,
+            HTTP_TIMEOUT: get_env_as_parse_or("HTTP_TIMEOUT", 30)?,
+            HTTP_PROXY: get_env_or("HTTP_PROXY", ""),
+            HTTP_USER_AGENT: get_env_or("HTTP_USER_AGENT", DEFAULT_USER_AGENT),
+            HTTP_LISTEN_ADDR: get_env_or("HTTP_LISTEN_ADDR", "0.0.0.0:8080"),
+            FILES_PUBLIC_URL: get_env_or("FILES_PUBLIC_URL", ""),
+            METRICS_ENABLED: get_env_as_parse_or("METRICS_ENABLED", false)?,
+            PAYMENT_ENABLED: get_env_as_parse_or("PAYMENT_ENABLED", false)?,
+            PAYMENT_API_URL: get_env_or("PAYMENT_API_URL", ""),
+            PAYMENT_API_TOKEN: get_env_or("PAYMENT_API_TOKEN", ""),
+            PAYMENT_TIMEOUT: get_env_as_parse_or("PAYMENT_TIMEOUT", 3600)?,
         })
     }
+
+    #[cfg(test)]
+    fn for_tests() -> Config {
+        Config {
+            RUST_LOG: "info".to_string(),
+            LOG_FORMAT: "text".to_string(),
+            LOG_REDACT: true,
+            MESSENGER: "whatsapp".to_string(),
+            CHANNELS: String::new(),
+            API_URL: "http://localhost".to_string(),
+            ID_INSTANCE: "1".to_string(),
+            API_TOKEN_INSTANCE: "token".to_string(),
+            ADMIN_CHAT_ID: "79140000000@c.us".to_string(),
+            ORDER_SINK: "worker".to_string(),
+            WORKER_URL: "http://localhost/orders".to_string(),
+            WORKER_API_VERSION: 1,
+            WORKER_AUTH: "none".to_string(),
+            WORKER_SECRET: String::new(),
+            HOT_FOLDER_DIR: std::env::temp_dir()
+                .join("astrafoto-hotfolder")
+                .to_string_lossy()
+                .into_owned(),
+            HOT_FOLDER_STAGING_DIR: std::env::temp_dir()
+                .join("astrafoto-hotfolder-staging")
+                .to_string_lossy()
+                .into_owned(),
+            HOT_FOLDER_MANIFEST: "json".to_string(),
+            ORDER_EMAIL_TO: String::new(),
+            TELEGRAM_API_URL: "http://localhost".to_string(),
+            TELEGRAM_BOT_TOKEN: "token".to_string(),
+            QUARANTINE_DIR: std::env::temp_dir()
+                .join("astrafoto-quarantine")
+                .to_string_lossy()
+                .into_owned(),
+            AUDIT_LOG_FILE: std::env::temp_dir()
+                .join("astrafoto-audit.jsonl")
+                .to_string_lossy()
+                .into_owned(),
+            REPORT_TIME: String::new(),
+            HEALTH_CHECK_INTERVAL: 60,
+            ALERT_CHANNEL: "log".to_string(),
+            ALERT_LOG_FILE: std::env::temp_dir()
+                .join("astrafoto-alerts.log")
+                .to_string_lossy()
+                .into_owned(),
+            EMAIL_FROM: String::new(),
+            ALERT_EMAIL_TO: String::new(),
+            SMTP_HOST: String::new(),
+            SMTP_PORT: 587,
+            SMTP_USER: String::new(),
+            SMTP_PASSWORD: String::new(),
+            SHOP_ADDRESS: "ул. Тестовая, 1".to_string(),
+            SHOP_PHONE: "+79140000000".to_string(),
+            FAQ_FILE: "faq.json".to_string(),
+            SHOP_TIMEZONE: "UTC".to_string(),
+            SHOP_HOURS: "00:00-24:00".to_string(),
+            SHOP_HOLIDAYS: String::new(),
+            PRODUCTION_MINUTES: 60,
+            PRODUCTION_CAPACITY: String::new(),
+            PRODUCTION_DEFAULT_RATE: 100,
+            OFF_HOURS_NOTICE: false,
+            HOLD_OFF_HOURS: false,
+            NO_FILES_TIMEOUT: 60,
+            REPEAT_COUNT: 3,
+            REPEAT_TIMEOUT: 30,
+            OUTBOX_GLOBAL_INTERVAL_MS: 1000,
+            OUTBOX_CHAT_INTERVAL_MS: 2000,
+            OUTBOX_RETRY_MS: 2000,
+            OUTBOX_MAX_RETRIES: 5,
+            HTTP_CONNECT_TIMEOUT: 5,
+            HTTP_TIMEOUT: 30,
+            HTTP_PROXY: String::new(),
+            HTTP_USER_AGENT: DEFAULT_USER_AGENT.to_string(),
+            HTTP_LISTEN_ADDR: "127.0.0.1:0".to_string(),
+            FILES_PUBLIC_URL: "http://localhost:8080/files".to_string(),
+            METRICS_ENABLED: false,
+            PAYMENT_ENABLED: false,
+            PAYMENT_API_URL: String::new(),
+            PAYMENT_API_TOKEN: String::new(),
+            PAYMENT_TIMEOUT: 3600,
+        }
+    }
 }
 
+#[cfg(not(test))]
 fn get_env(name: &'static str) -> Result<String> {
     env::var(name).map_err(|_| Error::ConfigMissingEnv(name))
 }
 
+#[cfg(not(test))]
 fn get_env_as_parse<T: FromStr>(name: &'static str) -> Result<T> {
     let val = get_env(name)?;
     val.parse::<T>().map_err(|_| Error::ConfigWrongFormat(name))
-}
\ No newline at end of file
+}
+
+#[cfg(not(test))]
+fn get_env_or(name: &'static str, default: &str) -> String {
+    env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+#[cfg(not(test))]
+fn get_env_as_parse_or<T: FromStr>(name: &'static str, default: T) -> Result<T> {
+    match env::var(name) {
+        Ok(val) => val.parse::<T>().map_err(|_| Error::ConfigWrongFormat(name)),
+        Err(_) => Ok(default),
+    }
+}
@@ -1,8 +1,23 @@
 pub use crate::error::Result;
+use crate::config::config;
+use crate::error::Error;
+use crate::stuff::alert::Alerter;
+use crate::stuff::audit::{AuditLog, AuditedRepository, AuditedTransport};
+use crate::stuff::files::FileStore;
+use crate::stuff::health::HealthMonitor;
 use crate::stuff::message_handler::Handler;
+use crate::stuff::multi::MultiTransport;
+use crate::stuff::outbox::{Outbox, OutboxLimits};
+use crate::stuff::payment::HttpPaymentProvider;
 use crate::stuff::poller::Poller;
+use crate::stuff::report::{DailyReporter, SalesLog};
 use crate::stuff::repository::OrderRepository;
-use crate::stuff::transport::WhatsApp;
+use crate::stuff::sink::Sink;
+use crate::stuff::{http, logging, server};
+use crate::stuff::telegram::Telegram;
+use crate::stuff::transport::{Transport, WhatsApp};
+use crate::stuff::worker_auth::WorkerAuth;
+use tokio::sync::mpsc;
 
 mod config;
 mod error;
@@ -10,10 +25,88 @@ mod stuff;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let transport = WhatsApp::new();
-    pretty_env_logger::init_timed();
-    let repo = OrderRepository::new();
-    let handler = Handler::new(repo, &transport);
-    Poller::new(&transport, handler).start_polling().await?;
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, command, chat, rest @ ..] = args.as_slice()
+        && command == "transcript"
+    {
+        let format = rest.first().map(String::as_str).unwrap_or("text");
+        print!("{}", AuditLog::from_config().export(chat, format)?);
+        return Ok(());
+    }
+    if let [_, command, from, rest @ ..] = args.as_slice()
+        && command == "report"
+    {
+        let to = rest.first().map(String::as_str);
+        print!("{}", SalesLog::from_config().export(from, to)?);
+        return Ok(());
+    }
+    let http = http::build_client()?;
+    logging::init()?;
+    let files = FileStore::new(http.clone());
+    if !config().CHANNELS.is_empty() {
+        let transport = MultiTransport::from_config(&http)?;
+        let files = transport
+            .telegram_files()
+            .into_iter()
+            .fold(files, |files, (name, bot)| files.with_telegram(&name, bot));
+        return tokio::select! {
+            res = run(&transport, http, files) => res,
+            _ = transport.run() => Ok(()),
+        };
+    }
+    match config().MESSENGER.as_str() {
+        "whatsapp" => run(&WhatsApp::new(http.clone()), http, files).await,
+        "telegram" => {
+            let telegram = Telegram::new(http.clone());
+            let files = files.with_telegram("", telegram.files());
+            run(&telegram, http, files).await
+        }
+        _ => Err(Error::ConfigWrongFormat("MESSENGER")),
+    }
+}
+
+async fn run<T: Transport>(transport: &T, http: reqwest::Client, files: FileStore) -> Result<()> {
+    let alerter = Alerter::from_config()?;
+    let audit = AuditLog::from_config();
+    let repo = AuditedRepository::new(OrderRepository::new(), audit.clone());
+    let (payment_tx, payment_rx) = mpsc::unbounded_channel();
+    let (worker_tx, worker_rx) = mpsc::unbounded_channel();
+    let payments = config()
+        .PAYMENT_ENABLED
+        .then(|| HttpPaymentProvider::new(http.clone()));
+    let worker_auth = WorkerAuth::from_config()?;
+    // Callback сервиса печати принимаются только с настроенной подписью,
+    // /metrics и /files подключаются, только если включены в настройках
+    let file_proxy = (!config().FILES_PUBLIC_URL.is_empty()).then(|| files.clone());
+    if payments.is_some()
+        || worker_auth.is_enabled()
+        || config().METRICS_ENABLED
+        || file_proxy.is_some()
+    {
+        tokio::spawn(server::serve(
+            config().HTTP_LISTEN_ADDR.clone(),
+            payment_tx,
+            worker_tx,
+            worker_auth,
+            file_proxy,
+        ));
+    }
+    let sales = SalesLog::new(audit.clone());
+    let audited = AuditedTransport::new(transport, audit);
+    let (mut outbox, queued) = Outbox::new(&audited, OutboxLimits::from_config());
+    let delivery_failures = outbox.delivery_failures();
+    let sink = Sink::from_config(http.clone(), files)?;
+    let reporter = DailyReporter::from_config(&queued, sales.clone())?;
+    let handler = Handler::new(repo, &queued, sink, payments).with_sales_log(sales);
+    let mut poller = Poller::new(&queued, handler)
+        .with_payment_events(payment_rx)
+        .with_delivery_failures(delivery_failures)
+        .with_worker_events(worker_rx);
+    tokio::select! {
+        res = poller.start_polling() => res?,
+        _ = outbox.run() => {}
+        _ = HealthMonitor::new(transport, alerter).run() => {}
+        _ = reporter.run() => {}
+    }
     Ok(())
 }
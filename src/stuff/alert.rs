@@ -0,0 +1,50 @@
+use crate::config::config;
+use crate::stuff::email::{Mailer, mailbox};
+use crate::stuff::error::{Error, Result};
+use chrono::Utc;
+use lettre::message::Mailbox;
+use log::error;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Резервный канал оповещения администратора, не зависящий от WhatsApp
+pub enum Alerter {
+    Email(Box<Mailer>, Mailbox),
+    LogFile(PathBuf),
+}
+
+impl Alerter {
+    /// ALERT_CHANNEL: "email" - письмо на ALERT_EMAIL_TO, "log" - запись в ALERT_LOG_FILE
+    pub fn from_config() -> Result<Alerter> {
+        match config().ALERT_CHANNEL.as_str() {
+            "email" => Ok(Alerter::Email(
+                Box::new(Mailer::from_config()?),
+                mailbox(&config().ALERT_EMAIL_TO)?,
+            )),
+            "log" => Ok(Alerter::LogFile(PathBuf::from(&config().ALERT_LOG_FILE))),
+            other => Err(Error::AlertInvalid(format!("ALERT_CHANNEL {}", other))),
+        }
+    }
+
+    /// Ошибки отправки только логируются: оповещение не должно останавливать бота
+    pub async fn alert(&self, subject: &str, text: &str) {
+        let res = match self {
+            Alerter::Email(mailer, to) => mailer.send(to, subject, text).await,
+            Alerter::LogFile(path) => append_alert(path, subject, text),
+        };
+        if let Err(e) = res {
+            error!("[alert] {}: {} - {}", subject, text, e);
+        }
+    }
+}
+
+fn append_alert(path: &PathBuf, subject: &str, text: &str) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| Error::AlertFailed(e.to_string()))?;
+    writeln!(file, "{} [{}] {}", Utc::now().to_rfc3339(), subject, text)
+        .map_err(|e| Error::AlertFailed(e.to_string()))
+}
@@ -0,0 +1,421 @@
+use crate::config::config;
+use crate::stuff::data_types::{InstanceState, Message, Order, phone};
+use crate::stuff::error::{Error, Result};
+use crate::stuff::money::Money;
+use crate::stuff::report::SaleEvent;
+use crate::stuff::repository::Repository;
+use crate::stuff::transport::Transport;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Строка журнала переписки
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub time: DateTime<Utc>,
+    pub chat_id: String,
+    #[serde(flatten)]
+    pub entry: AuditEntry,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AuditEntry {
+    /// Сообщение клиента: текст или ссылка на фото
+    Inbound {
+        message_id: Option<String>,
+        text: Option<String>,
+        file: Option<String>,
+    },
+    Call,
+    /// Ответ бота, `error` - мессенджер не принял сообщение
+    Outbound {
+        message_id: Option<String>,
+        text: String,
+        error: Option<String>,
+    },
+    /// Заказ перешел на этап `stage`, у закрытого заказа этапа нет
+    Stage {
+        order_id: String,
+        from: Option<String>,
+        stage: Option<String>,
+        selections: Vec<String>,
+    },
+    /// Событие заказа для отчетов о продажах
+    Sale {
+        order_id: String,
+        event: SaleEvent,
+        paper_type: String,
+        paper_size: String,
+        photos: usize,
+        total: Option<Money>,
+    },
+}
+
+/// Журнал только дописывается, по строке JSON на запись
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn from_config() -> Self {
+        AuditLog::new(&config().AUDIT_LOG_FILE)
+    }
+
+    /// Сбой журнала не мешает обработке заказа, остается только лог
+    pub fn append(&self, chat_id: &str, entry: AuditEntry) {
+        let record = AuditRecord {
+            time: Utc::now(),
+            chat_id: chat_id.to_string(),
+            entry,
+        };
+        if let Err(e) = self.write(&record) {
+            error!("[audit] {}", e);
+        }
+    }
+
+    fn write(&self, record: &AuditRecord) -> Result<()> {
+        let mut line =
+            serde_json::to_string(record).map_err(|e| Error::AuditFailed(e.to_string()))?;
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| Error::AuditFailed(e.to_string()))?;
+        // Одна запись за вызов, чтобы строки не перемешивались
+        file.write_all(line.as_bytes())
+            .map_err(|e| Error::AuditFailed(e.to_string()))
+    }
+
+    /// Все записи журнала, поврежденные строки пропускаются
+    pub fn records(&self) -> Result<Vec<AuditRecord>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            // Журнал еще не создан
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(Error::AuditFailed(e.to_string())),
+        };
+        let mut records = vec![];
+        for (idx, line) in content.lines().enumerate() {
+            match serde_json::from_str::<AuditRecord>(line) {
+                Ok(record) => records.push(record),
+                Err(e) => warn!("[audit] line {} skipped: {}", idx + 1, e),
+            }
+        }
+        Ok(records)
+    }
+
+    /// Записи одного чата, `chat` - идентификатор чата или номер телефона
+    pub fn transcript(&self, chat: &str) -> Result<Vec<AuditRecord>> {
+        let mut records = self.records()?;
+        records.retain(|record| record.chat_id == chat || phone(&record.chat_id) == chat);
+        Ok(records)
+    }
+
+    /// Переписка в формате `text` или `json` для разбора жалобы клиента
+    pub fn export(&self, chat: &str, format: &str) -> Result<String> {
+        let records = self.transcript(chat)?;
+        match format {
+            "text" => Ok(format_transcript(&records, shop_timezone())),
+            "json" => serde_json::to_string_pretty(&records)
+                .map_err(|e| Error::AuditFailed(e.to_string())),
+            _ => Err(Error::AuditFailed(format!("unknown format {}", format))),
+        }
+    }
+}
+
+fn shop_timezone() -> Tz {
+    config().SHOP_TIMEZONE.parse().unwrap_or(Tz::UTC)
+}
+
+pub fn format_transcript(records: &[AuditRecord], tz: Tz) -> String {
+    let mut output = String::new();
+    for record in records {
+        let time = record.time.with_timezone(&tz).format("%d.%m.%Y %H:%M:%S");
+        let line = match &record.entry {
+            AuditEntry::Inbound {
+                message_id,
+                text,
+                file,
+            } => {
+                let content = match (text, file) {
+                    (Some(text), _) => text.clone(),
+                    (None, Some(file)) => format!("фото {}", file),
+                    (None, None) => String::new(),
+                };
+                format!("<- {}{}", id_prefix(message_id), content)
+            }
+            AuditEntry::Call => "<- звонок".to_string(),
+            AuditEntry::Outbound {
+                message_id,
+                text,
+                error,
+            } => match error {
+                Some(error) => format!("-> {} (не доставлено: {})", text, error),
+                None => format!("-> {}{}", id_prefix(message_id), text),
+            },
+            AuditEntry::Stage {
+                order_id,
+                from,
+                stage,
+                selections,
+            } => format!(
+                "== заказ {}: {} -> {} [{}]",
+                order_id,
+                from.as_deref().unwrap_or("новый"),
+                stage.as_deref().unwrap_or("закрыт"),
+                selections.join(", ")
+            ),
+            AuditEntry::Sale {
+                order_id,
+                event,
+                photos,
+                total,
+                ..
+            } => format!(
+                "== заказ {}: {}, фото {}{}",
+                order_id,
+                event.name(),
+                photos,
+                total.map(|t| format!(", {}", t)).unwrap_or_default()
+            ),
+        };
+        // Многострочные ответы бота сдвигаются под время
+        let _ = writeln!(
+            output,
+            "{} {}",
+            time,
+            line.replace('\n', "\n                    ")
+        );
+    }
+    output
+}
+
+fn id_prefix(message_id: &Option<String>) -> String {
+    match message_id {
+        Some(id) => format!("[{}] ", id),
+        None => String::new(),
+    }
+}
+
+/// Записывает в журнал входящие и исходящие сообщения
+pub struct AuditedTransport<'a, T: Transport> {
+    inner: &'a T,
+    audit: AuditLog,
+}
+
+impl<'a, T: Transport> AuditedTransport<'a, T> {
+    pub fn new(inner: &'a T, audit: AuditLog) -> Self {
+        Self { inner, audit }
+    }
+}
+
+impl<T: Transport> Transport for AuditedTransport<'_, T> {
+    async fn receive_message(&self) -> Result<Message> {
+        let message = self.inner.receive_message().await?;
+        match &message {
+            Message::Text(m) => self.audit.append(
+                &m.chat_id,
+                AuditEntry::Inbound {
+                    message_id: m.message_id.clone(),
+                    text: Some(m.message.clone()),
+                    file: None,
+                },
+            ),
+            Message::Image(m) => self.audit.append(
+                &m.chat_id,
+                AuditEntry::Inbound {
+                    message_id: m.message_id.clone(),
+                    text: None,
+                    file: Some(m.file.url.clone()),
+                },
+            ),
+            Message::Call(chat_id) => self.audit.append(chat_id, AuditEntry::Call),
+            _ => {}
+        }
+        Ok(message)
+    }
+
+    async fn send_message(&self, chat_id: String, msg: String) -> Result<()> {
+        self.send_message_id(chat_id, msg).await.map(|_| ())
+    }
+
+    async fn send_message_id(&self, chat_id: String, msg: String) -> Result<Option<String>> {
+        let res = self
+            .inner
+            .send_message_id(chat_id.clone(), msg.clone())
+            .await;
+        let (message_id, error) = match &res {
+            Ok(id) => (id.clone(), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        self.audit.append(
+            &chat_id,
+            AuditEntry::Outbound {
+                message_id,
+                text: msg,
+                error,
+            },
+        );
+        res
+    }
+
+    async fn instance_state(&self) -> Result<InstanceState> {
+        self.inner.instance_state().await
+    }
+
+    async fn channel_states(&self) -> Vec<(String, Result<InstanceState>)> {
+        self.inner.channel_states().await
+    }
+}
+
+/// Записывает в журнал смену этапа и выбранных вариантов заказа
+pub struct AuditedRepository<R: Repository> {
+    inner: R,
+    audit: AuditLog,
+}
+
+impl<R: Repository> AuditedRepository<R> {
+    pub fn new(inner: R, audit: AuditLog) -> Self {
+        Self { inner, audit }
+    }
+
+    fn append_stage(&self, order: &Order, from: Option<&Order>, closed: bool) {
+        let entry = AuditEntry::Stage {
+            order_id: order.trace_id.clone(),
+            from: from.map(|o| o.stage.name().to_string()),
+            stage: (!closed).then(|| order.stage.name().to_string()),
+            selections: order
+                .selections
+                .iter()
+                .map(|s| format!("{}: {}", s.group, s.choice))
+                .collect(),
+        };
+        self.audit.append(&order.chat_id, entry);
+    }
+}
+
+impl<R: Repository> Repository for AuditedRepository<R> {
+    fn get_order(&self, chat_id: &str) -> Option<Order> {
+        self.inner.get_order(chat_id)
+    }
+
+    fn get_orders(&self) -> HashMap<String, Order> {
+        self.inner.get_orders()
+    }
+
+    fn set_order(&mut self, state: Order) {
+        let previous = self.inner.get_order(&state.chat_id);
+        let changed = previous
+            .as_ref()
+            .is_none_or(|p| p.stage != state.stage || p.selections != state.selections);
+        if changed {
+            self.append_stage(&state, previous.as_ref(), false);
+        }
+        self.inner.set_order(state);
+    }
+
+    fn delete_order(&mut self, chat_id: &str) -> Result<()> {
+        let previous = self.inner.get_order(chat_id);
+        self.inner.delete_order(chat_id)?;
+        if let Some(order) = previous {
+            self.append_stage(&order, Some(&order), true);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stuff::data_types::{OrderEvent, ReceivedMessage};
+    use crate::stuff::repository::OrderRepository;
+    use crate::stuff::transport::MockTransport;
+
+    #[tokio::test]
+    async fn transcript_test() {
+        let path =
+            std::env::temp_dir().join(format!("astrafoto-audit-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let audit = AuditLog::new(&path);
+        let chat_id = "79146796712@c.us";
+
+        let transport = AuditedTransport::new(&MockTransport, audit.clone());
+        transport.receive_message().await.unwrap();
+        transport
+            .send_message(
+                chat_id.to_string(),
+                "Выберите товар:\n1. Печать фото".to_string(),
+            )
+            .await
+            .unwrap();
+        let mut repo = AuditedRepository::new(OrderRepository::new(), audit.clone());
+        let order = Order::from_txt_msg(ReceivedMessage {
+            chat_id: chat_id.to_string(),
+            customer_name: "Andrey".to_string(),
+            message_id: None,
+            message: "Привет".to_string(),
+        });
+        repo.set_order(order.clone());
+        // Повторное сохранение без изменений в журнал не попадает
+        repo.set_order(order.clone());
+        repo.set_order(
+            order
+                .clone()
+                .apply(OrderEvent::ProductChosen("Фото".to_string()))
+                .unwrap(),
+        );
+        repo.delete_order(chat_id).unwrap();
+        audit.append("79990000000@c.us", AuditEntry::Call);
+
+        let records = audit.transcript("79146796712").unwrap();
+        let kinds: Vec<_> = records
+            .iter()
+            .map(|r| match &r.entry {
+                AuditEntry::Inbound { .. } => "inbound".to_string(),
+                AuditEntry::Call => "call".to_string(),
+                AuditEntry::Outbound { .. } => "outbound".to_string(),
+                AuditEntry::Stage { from, stage, .. } => format!("{:?}->{:?}", from, stage),
+                AuditEntry::Sale { event, .. } => event.name().to_string(),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "inbound",
+                "outbound",
+                "None->Some(\"ProductRequested\")",
+                "Some(\"ProductRequested\")->Some(\"OptionsRequested\")",
+                "Some(\"OptionsRequested\")->None",
+            ]
+        );
+
+        let text = format_transcript(&records, Tz::UTC);
+        assert!(text.contains(" <- hi\n"));
+        assert!(text.contains(" -> Выберите товар:\n                    1. Печать фото\n"));
+        assert!(text.contains(&format!(
+            "== заказ {}: новый -> ProductRequested []",
+            order.trace_id
+        )));
+        let json = audit.export(chat_id, "json").unwrap();
+        assert_eq!(
+            serde_json::from_str::<Vec<AuditRecord>>(&json)
+                .unwrap()
+                .len(),
+            5
+        );
+        let _ = fs::remove_file(&path);
+    }
+}
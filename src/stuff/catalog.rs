@@ -0,0 +1,243 @@
+use crate::stuff::data_types::Fit;
+use crate::stuff::error::Result;
+use crate::stuff::money::{Currency, Money};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::Read;
+
+/// Товар, например фотопечать или фотомагнит.
+/// Варианты исполнения выбираются последовательно по группам `options`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Product {
+    pub name: String,
+    pub price: Money,
+    pub options: Vec<OptionGroup>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OptionGroup {
+    /// Служебное имя группы для обработчика заказов: paper, size...
+    pub key: Option<String>,
+    pub name: String,
+    pub prompt: String,
+    pub choices: Vec<Choice>,
+}
+
+/// Вариант выбора. Цена прибавляется к цене товара,
+/// вложенные группы запрашиваются сразу после выбора варианта.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Choice {
+    pub name: String,
+    pub price: Money,
+    pub options: Vec<OptionGroup>,
+    /// Вариант сам задает кадрирование, например печать без полей всегда обрезается
+    pub fit: Option<Fit>,
+}
+
+/// Выбранный клиентом вариант в группе
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Selection {
+    pub group: String,
+    pub key: Option<String>,
+    pub choice: String,
+    pub price: Money,
+    pub fit: Option<Fit>,
+}
+
+// region:    --- catalog.json
+#[derive(Deserialize)]
+struct CatalogFile {
+    currency: Currency,
+    products: Vec<ProductFile>,
+}
+
+#[derive(Deserialize)]
+struct ProductFile {
+    name: String,
+    #[serde(default)]
+    price: Option<serde_json::Number>,
+    #[serde(default)]
+    options: Vec<OptionGroupFile>,
+}
+
+#[derive(Deserialize)]
+struct OptionGroupFile {
+    key: Option<String>,
+    name: String,
+    prompt: Option<String>,
+    choices: Vec<ChoiceFile>,
+}
+
+/// Цены указываются в основных единицах валюты, допускаются копейки: 22.5
+#[derive(Deserialize)]
+struct ChoiceFile {
+    name: String,
+    #[serde(default)]
+    price: Option<serde_json::Number>,
+    #[serde(default)]
+    options: Vec<OptionGroupFile>,
+    #[serde(default)]
+    fit: Option<Fit>,
+}
+
+/// Большие числа serde_json выводит в экспоненциальной записи, их разбираем как f64
+fn decode_price(price: Option<serde_json::Number>, currency: Currency) -> Result<Money> {
+    match price {
+        Some(p) => Money::parse(&p.to_string(), currency).or_else(|e| match p.as_f64() {
+            Some(value) => Money::from_major(value, currency),
+            None => Err(e),
+        }),
+        None => Ok(Money::new(0, currency)),
+    }
+}
+
+fn decode_groups(groups: Vec<OptionGroupFile>, currency: Currency) -> Result<Vec<OptionGroup>> {
+    groups
+        .into_iter()
+        .map(|g| {
+            Ok(OptionGroup {
+                prompt: g.prompt.unwrap_or_else(|| format!("Выберите: {}", g.name)),
+                key: g.key,
+                name: g.name,
+                choices: g
+                    .choices
+                    .into_iter()
+                    .map(|c| {
+                        Ok(Choice {
+                            name: c.name,
+                            price: decode_price(c.price, currency)?,
+                            options: decode_groups(c.options, currency)?,
+                            fit: c.fit,
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            })
+        })
+        .collect()
+}
+
+fn decode_products(products: Vec<ProductFile>, currency: Currency) -> Result<Vec<Product>> {
+    products
+        .into_iter()
+        .map(|p| {
+            Ok(Product {
+                price: decode_price(p.price, currency)?,
+                options: decode_groups(p.options, currency)?,
+                name: p.name,
+            })
+        })
+        .collect()
+}
+// endregion: --- catalog.json
+
+pub struct Catalog {
+    products: Vec<Product>,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Catalog::load_from_file()
+    }
+
+    fn load_from_file() -> Catalog {
+        let mut file = File::open("catalog.json").expect("catalog.json not found");
+        let mut buffer = String::new();
+        file.read_to_string(&mut buffer)
+            .expect("catalog.json read error");
+        let decoded =
+            serde_json::from_str::<CatalogFile>(&buffer).expect("catalog.json decode error");
+        let currency = decoded.currency;
+        let products = decode_products(decoded.products, currency)
+            .unwrap_or_else(|e| panic!("catalog.json price invalid: {}", e));
+        Catalog { products }
+    }
+
+    pub fn products(&self) -> &[Product] {
+        &self.products
+    }
+
+    pub fn product(&self, name: &str) -> Option<&Product> {
+        self.products.iter().find(|p| p.name.eq(name))
+    }
+
+    /// Группа, вариант в которой клиент должен выбрать следующим.
+    /// `None` - все варианты выбраны.
+    pub fn next_group(&self, product: &str, selections: &[Selection]) -> Option<&OptionGroup> {
+        let mut pending: Vec<&OptionGroup> = self.product(product)?.options.iter().rev().collect();
+        for selection in selections {
+            let group = pending.pop()?;
+            let choice = group.choices.iter().find(|c| c.name.eq(&selection.choice))?;
+            pending.extend(choice.options.iter().rev());
+        }
+        pending.pop()
+    }
+
+    pub fn select(&self, product: &str, selections: &[Selection], idx: usize) -> Option<Selection> {
+        let group = self.next_group(product, selections)?;
+        let choice = group.choices.get(idx)?;
+        Some(Selection {
+            group: group.name.clone(),
+            key: group.key.clone(),
+            choice: choice.name.clone(),
+            price: choice.price,
+            fit: choice.fit,
+        })
+    }
+
+    /// Цена одной единицы товара с учетом выбранных вариантов.
+    /// Все цены каталога в одной валюте, поэтому сложение не завершается ошибкой
+    pub fn price(&self, product: &str, selections: &[Selection]) -> Option<Money> {
+        let base = self.product(product)?.price;
+        selections
+            .iter()
+            .try_fold(base, |sum, s| sum.checked_add(s.price))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn get_catalog_test() {
+        let catalog = Catalog::new();
+        assert_eq!(catalog.products().len(), 3);
+    }
+
+    #[test]
+    fn decode_price_test() {
+        let price = |json: &str| decode_price(serde_json::from_str(json).ok(), Currency::Rub);
+        assert_eq!(price("22.5").unwrap().amount, 2250);
+        assert_eq!(price("1e5").unwrap().amount, 10000000);
+        assert_eq!(price("1e16").unwrap().amount, 10i64.pow(18));
+        assert!(price("1e20").is_err());
+        assert!(price("0.001").is_err());
+    }
+
+    #[test]
+    fn walk_options_test() {
+        let catalog = Catalog::new();
+        let product = "Фотопечать";
+        let mut selections = vec![];
+
+        let group = catalog.next_group(product, &selections).unwrap();
+        assert_eq!(group.key.as_deref(), Some("paper"));
+        selections.push(catalog.select(product, &selections, 0).unwrap());
+
+        let group = catalog.next_group(product, &selections).unwrap();
+        assert_eq!(group.key.as_deref(), Some("size"));
+        selections.push(catalog.select(product, &selections, 0).unwrap());
+
+        let group = catalog.next_group(product, &selections).unwrap();
+        assert_eq!(group.key.as_deref(), Some("border"));
+        assert!(catalog.select(product, &selections, 10).is_none());
+        selections.push(catalog.select(product, &selections, 1).unwrap());
+
+        assert!(catalog.next_group(product, &selections).is_none());
+        assert_eq!(
+            catalog.price(product, &selections),
+            Some(Money::new(2200, Currency::Rub))
+        );
+    }
+}
@@ -1,294 +1,645 @@
-use crate::stuff::error::{Error, Result};
-use serde::Serialize;
+use crate::stuff::catalog::Selection;
+use crate::stuff::error::Error;
+use crate::stuff::files::public_url;
+use crate::stuff::image_info::Dimensions;
+use crate::stuff::logging;
+use crate::stuff::money::Money;
+use crate::stuff::payment::Payment;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 use std::time::SystemTime;
 
 #[derive(Debug, Clone)]
 pub enum Message {
     Text(ReceivedMessage),
-    Image(ReceivedMessage),
+    Image(ReceivedImage),
+    /// Статус доставки отправленного ботом сообщения
+    Status(MessageStatus),
+    /// Изменилось состояние канала: имя канала, пустое без CHANNELS, и его состояние
+    InstanceState(String, InstanceState),
+    /// Клиент позвонил в WhatsApp
+    Call(String),
     Empty,
 }
 
+/// Номер телефона из идентификатора чата без имени канала и домена
+pub fn phone(chat_id: &str) -> &str {
+    let chat_id = chat_id.rsplit(':').next().unwrap_or_default();
+    chat_id.split('@').next().unwrap_or_default()
+}
+
+impl Message {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Message::Text(_) => "Text",
+            Message::Image(_) => "Image",
+            Message::Status(_) => "Status",
+            Message::InstanceState(..) => "InstanceState",
+            Message::Call(_) => "Call",
+            Message::Empty => "Empty",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MessageStatus {
+    pub chat_id: String,
+    pub message_id: String,
+    pub status: DeliveryStatus,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DeliveryStatus {
+    Sent,
+    Delivered,
+    Read,
+    Failed,
+    /// У номера нет WhatsApp
+    NoAccount,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum InstanceState {
+    Authorized,
+    NotAuthorized,
+    Blocked,
+    SleepMode,
+    Starting,
+    /// Подозрение на спам, отправка ограничена
+    YellowCard,
+    Other,
+}
+
 #[derive(Debug, Clone)]
 pub struct ReceivedMessage {
     pub chat_id: String,
     pub customer_name: String,
+    /// Идентификатор сообщения в мессенджере, если известен
+    pub message_id: Option<String>,
     pub message: String,
 }
 
+#[derive(Debug, Clone)]
+pub struct ReceivedImage {
+    pub chat_id: String,
+    pub customer_name: String,
+    /// Идентификатор сообщения в мессенджере, если известен
+    pub message_id: Option<String>,
+    pub file: OrderFile,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OrderFile {
+    pub url: String,
+    /// Размеры изображения, если их удалось определить
+    pub dimensions: Option<Dimensions>,
+}
+
+/// Способ печати фото, пропорции которого не совпадают с форматом
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fit {
+    /// Обрезать по формату, заполнив весь лист
+    Crop,
+    /// Вписать целиком, оставив белые поля
+    Fit,
+}
+
+/// Заказ клиента: общие данные и текущий этап оформления
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Order {
+    pub chat_id: String,
+    pub customer_name: String,
+    pub files: Vec<OrderFile>,
+    /// Выбранные варианты товара в порядке выбора
+    pub selections: Vec<Selection>,
+    pub stage: OrderStage,
+    pub repeats: i32,
+    pub last_msg_time: SystemTime,
+    /// Ориентировочное время готовности, рассчитывается при передаче в работу
+    pub ready_at: Option<DateTime<Utc>>,
+    /// Сквозной идентификатор заказа в логах и запросах к сервису печати
+    pub trace_id: String,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
-pub enum OrderState {
-    RaperRequested {
-        chat_id: String,
-        customer_name: String,
-        files: Vec<String>,
-        repeats: i32,
-        last_msg_time: SystemTime,
+pub enum OrderStage {
+    ProductRequested,
+    OptionsRequested {
+        product: String,
+    },
+    /// Клиент выбирает, обрезать фото или вписать с полями
+    FitRequested {
+        product: String,
+        price: Money,
     },
-    SizeRequested {
-        chat_id: String,
-        customer_name: String,
-        paper: String,
-        files: Vec<String>,
-        repeats: i32,
-        last_msg_time: SystemTime,
+    /// Заказ собран и ждет подтверждения словом "Готово"
+    Ready {
+        product: String,
+        price: Money,
+        fit: Option<Fit>,
     },
-    SizeSelected {
-        chat_id: String,
-        customer_name: String,
-        paper: String,
-        size: String,
-        price: i32,
-        files: Vec<String>,
-        repeats: i32,
-        last_msg_time: SystemTime,
+    PaymentRequested {
+        product: String,
+        price: Money,
+        fit: Option<Fit>,
+        payment: Payment,
+        paid: bool,
     },
 }
 
-impl OrderState {
-    pub fn from_img_msg(msg: ReceivedMessage) -> OrderState {
-        OrderState::RaperRequested {
-            chat_id: msg.chat_id,
-            customer_name: msg.customer_name,
-            files: vec![msg.message],
-            repeats: 0,
-            last_msg_time: SystemTime::now(),
+#[derive(Debug, Clone)]
+pub enum OrderEvent {
+    ProductChosen(String),
+    OptionChosen(Selection),
+    /// Все варианты выбраны; `fit_required` - формат печати требует выбора кадрирования
+    /// `fit` - кадрирование, заданное выбранным вариантом, тогда оно не запрашивается
+    OptionsCompleted {
+        price: Money,
+        fit_required: bool,
+        fit: Option<Fit>,
+    },
+    FitChosen(Fit),
+    PaymentCreated(Payment),
+    PaymentSucceeded,
+    PaymentFailed,
+    /// Вернуться на предыдущий шаг
+    Back,
+    ChangeProduct,
+    /// Выбрать заново вариант с указанным номером и все следующие за ним
+    ChangeOption(usize),
+    ChangeFit,
+}
+
+/// Событие не допустимо на текущем этапе заказа
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TransitionError {
+    pub stage: &'static str,
+    pub event: &'static str,
+}
+
+impl OrderStage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OrderStage::ProductRequested => "ProductRequested",
+            OrderStage::OptionsRequested { .. } => "OptionsRequested",
+            OrderStage::FitRequested { .. } => "FitRequested",
+            OrderStage::Ready { .. } => "Ready",
+            OrderStage::PaymentRequested { .. } => "PaymentRequested",
+        }
+    }
+}
+
+impl OrderEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OrderEvent::ProductChosen(_) => "ProductChosen",
+            OrderEvent::OptionChosen(_) => "OptionChosen",
+            OrderEvent::OptionsCompleted { .. } => "OptionsCompleted",
+            OrderEvent::FitChosen(_) => "FitChosen",
+            OrderEvent::PaymentCreated(_) => "PaymentCreated",
+            OrderEvent::PaymentSucceeded => "PaymentSucceeded",
+            OrderEvent::PaymentFailed => "PaymentFailed",
+            OrderEvent::Back => "Back",
+            OrderEvent::ChangeProduct => "ChangeProduct",
+            OrderEvent::ChangeOption(_) => "ChangeOption",
+            OrderEvent::ChangeFit => "ChangeFit",
         }
     }
+}
 
-    pub fn from_txt_msg(msg: ReceivedMessage) -> OrderState {
-        OrderState::RaperRequested {
-            chat_id: msg.chat_id,
-            customer_name: msg.customer_name,
-            files: vec![],
+impl Order {
+    pub fn new(chat_id: String, customer_name: String, files: Vec<OrderFile>) -> Order {
+        Order {
+            chat_id,
+            customer_name,
+            files,
+            selections: vec![],
+            stage: OrderStage::ProductRequested,
             repeats: 0,
             last_msg_time: SystemTime::now(),
+            ready_at: None,
+            trace_id: logging::new_id(),
         }
     }
 
-    pub fn get_chat_id(&self) -> String {
-        match self {
-            OrderState::RaperRequested { chat_id, .. } => chat_id.to_string(),
-            OrderState::SizeRequested { chat_id, .. } => chat_id.to_string(),
-            OrderState::SizeSelected { chat_id, .. } => chat_id.to_string(),
-        }
+    pub fn from_img_msg(msg: ReceivedImage) -> Order {
+        Order::new(msg.chat_id, msg.customer_name, vec![msg.file])
     }
 
-    pub fn get_paper(&self) -> &str {
-        match self {
-            OrderState::RaperRequested { .. } => "",
-            OrderState::SizeRequested { paper, .. } => paper,
-            OrderState::SizeSelected { .. } => "",
-        }
+    pub fn from_txt_msg(msg: ReceivedMessage) -> Order {
+        Order::new(msg.chat_id, msg.customer_name, vec![])
     }
 
-    pub fn last_time_sec(&self) -> u64 {
-        match self {
-            OrderState::RaperRequested { last_msg_time, .. } => {
-                last_msg_time.elapsed().unwrap().as_secs()
+    /// Единственная точка смены этапа заказа
+    pub fn apply(mut self, event: OrderEvent) -> Result<Order, TransitionError> {
+        let fit_preset = self.preset_fit().is_some();
+        let error = TransitionError {
+            stage: self.stage.name(),
+            event: event.name(),
+        };
+        self.stage = match (self.stage, event) {
+            (OrderStage::ProductRequested, OrderEvent::ProductChosen(product)) => {
+                self.selections.clear();
+                OrderStage::OptionsRequested { product }
             }
-            OrderState::SizeRequested { last_msg_time, .. } => {
-                last_msg_time.elapsed().unwrap().as_secs()
+            (OrderStage::OptionsRequested { product }, OrderEvent::OptionChosen(selection)) => {
+                self.selections.push(selection);
+                OrderStage::OptionsRequested { product }
             }
-            OrderState::SizeSelected { last_msg_time, .. } => {
-                last_msg_time.elapsed().unwrap().as_secs()
+            (
+                OrderStage::OptionsRequested { product },
+                OrderEvent::OptionsCompleted {
+                    price,
+                    fit_required: true,
+                    ..
+                },
+            ) => OrderStage::FitRequested { product, price },
+            (
+                OrderStage::OptionsRequested { product },
+                OrderEvent::OptionsCompleted {
+                    price,
+                    fit_required: false,
+                    fit,
+                },
+            ) => OrderStage::Ready {
+                product,
+                price,
+                fit,
+            },
+            (OrderStage::FitRequested { product, price }, OrderEvent::FitChosen(fit)) => {
+                OrderStage::Ready {
+                    product,
+                    price,
+                    fit: Some(fit),
+                }
+            }
+            (
+                OrderStage::Ready {
+                    product,
+                    price,
+                    fit,
+                },
+                OrderEvent::PaymentCreated(payment),
+            ) => OrderStage::PaymentRequested {
+                product,
+                price,
+                fit,
+                payment,
+                paid: false,
+            },
+            (
+                OrderStage::PaymentRequested {
+                    product,
+                    price,
+                    fit,
+                    payment,
+                    paid: false,
+                },
+                OrderEvent::PaymentSucceeded,
+            ) => OrderStage::PaymentRequested {
+                product,
+                price,
+                fit,
+                payment,
+                paid: true,
+            },
+            (
+                OrderStage::PaymentRequested {
+                    product,
+                    price,
+                    fit,
+                    paid: false,
+                    ..
+                },
+                OrderEvent::PaymentFailed,
+            ) => OrderStage::Ready {
+                product,
+                price,
+                fit,
+            },
+            (OrderStage::OptionsRequested { .. }, OrderEvent::Back)
+                if self.selections.is_empty() =>
+            {
+                OrderStage::ProductRequested
+            }
+            (
+                OrderStage::OptionsRequested { product }
+                | OrderStage::FitRequested { product, .. }
+                | OrderStage::Ready {
+                    product, fit: None, ..
+                },
+                OrderEvent::Back,
+            ) => {
+                self.selections.pop();
+                OrderStage::OptionsRequested { product }
             }
+            // Кадрирование задано вариантом и не запрашивалось
+            (OrderStage::Ready { product, .. }, OrderEvent::Back) if fit_preset => {
+                self.selections.pop();
+                OrderStage::OptionsRequested { product }
+            }
+            (
+                OrderStage::Ready {
+                    product,
+                    price,
+                    fit: Some(_),
+                },
+                OrderEvent::Back | OrderEvent::ChangeFit,
+            ) if !fit_preset => OrderStage::FitRequested { product, price },
+            (
+                OrderStage::OptionsRequested { .. }
+                | OrderStage::FitRequested { .. }
+                | OrderStage::Ready { .. },
+                OrderEvent::ChangeProduct,
+            ) => {
+                self.selections.clear();
+                OrderStage::ProductRequested
+            }
+            (
+                OrderStage::OptionsRequested { product }
+                | OrderStage::FitRequested { product, .. }
+                | OrderStage::Ready { product, .. },
+                OrderEvent::ChangeOption(idx),
+            ) if idx < self.selections.len() => {
+                self.selections.truncate(idx);
+                OrderStage::OptionsRequested { product }
+            }
+            _ => return Err(error),
+        };
+        self.repeats = 0;
+        self.last_msg_time = SystemTime::now();
+        Ok(self)
+    }
+
+    pub fn product(&self) -> Option<&str> {
+        match &self.stage {
+            OrderStage::ProductRequested => None,
+            OrderStage::OptionsRequested { product }
+            | OrderStage::FitRequested { product, .. }
+            | OrderStage::Ready { product, .. }
+            | OrderStage::PaymentRequested { product, .. } => Some(product),
         }
     }
 
-    pub fn repeats(&self) -> i32 {
-        match self {
-            OrderState::RaperRequested { repeats, .. } => *repeats,
-            OrderState::SizeRequested { repeats, .. } => *repeats,
-            OrderState::SizeSelected { repeats, .. } => *repeats,
+    /// Цена за штуку, известна после выбора всех вариантов
+    pub fn price(&self) -> Option<Money> {
+        match &self.stage {
+            OrderStage::ProductRequested | OrderStage::OptionsRequested { .. } => None,
+            OrderStage::FitRequested { price, .. }
+            | OrderStage::Ready { price, .. }
+            | OrderStage::PaymentRequested { price, .. } => Some(*price),
         }
     }
 
-    pub fn add_image(&mut self, url: String) {
-        match self {
-            OrderState::RaperRequested {
-                files,
-                last_msg_time,
-                ..
-            } => {
-                files.push(url);
-                *last_msg_time = SystemTime::now();
-            }
-            OrderState::SizeRequested {
-                files,
-                last_msg_time,
-                ..
-            } => {
-                files.push(url);
-                *last_msg_time = SystemTime::now();
-            }
-            OrderState::SizeSelected {
-                files,
-                last_msg_time,
-                ..
-            } => {
-                files.push(url);
-                *last_msg_time = SystemTime::now();
-            }
+    /// Кадрирование, заданное выбранными вариантами товара
+    pub fn preset_fit(&self) -> Option<Fit> {
+        self.selections.iter().find_map(|s| s.fit)
+    }
+
+    pub fn fit(&self) -> Option<Fit> {
+        match &self.stage {
+            OrderStage::Ready { fit, .. } | OrderStage::PaymentRequested { fit, .. } => *fit,
+            _ => None,
         }
     }
 
-    pub fn have_files(&self) -> bool {
-        match self {
-            OrderState::RaperRequested { files, .. } => !files.is_empty(),
-            OrderState::SizeRequested { files, .. } => !files.is_empty(),
-            OrderState::SizeSelected { files, .. } => !files.is_empty(),
+    pub fn total(&self) -> Option<Money> {
+        self.price()
+            .and_then(|p| p.checked_mul(self.files.len()).ok())
+    }
+
+    pub fn payment(&self) -> Option<&Payment> {
+        match &self.stage {
+            OrderStage::PaymentRequested { payment, .. } => Some(payment),
+            _ => None,
         }
     }
 
+    pub fn payment_id(&self) -> Option<&str> {
+        self.payment().map(|p| p.id.as_str())
+    }
+
+    pub fn is_paid(&self) -> bool {
+        matches!(self.stage, OrderStage::PaymentRequested { paid: true, .. })
+    }
+
+    /// Номер из идентификатора чата без имени канала и суффикса WhatsApp
+    pub fn phone(&self) -> &str {
+        phone(&self.chat_id)
+    }
+
+    pub fn last_time_sec(&self) -> u64 {
+        // При переводе часов назад считаем, что времени не прошло
+        self.last_msg_time
+            .elapsed()
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+
+    pub fn add_image(&mut self, file: OrderFile) {
+        self.files.push(file);
+        self.last_msg_time = SystemTime::now();
+    }
+
+    pub fn have_files(&self) -> bool {
+        !self.files.is_empty()
+    }
+
     pub fn files_count(&self) -> usize {
-        match self {
-            OrderState::RaperRequested { files, .. } => files.len(),
-            OrderState::SizeRequested { files, .. } => files.len(),
-            OrderState::SizeSelected { files, .. } => files.len(),
-        }
+        self.files.len()
     }
 
-    pub fn into_order_with_paper(self, paper: String) -> Result<OrderState> {
-        match self {
-            OrderState::RaperRequested {
-                chat_id,
-                customer_name,
-                files,
-                ..
-            } => Ok(OrderState::SizeRequested {
-                chat_id,
-                customer_name,
-                paper,
-                files,
-                repeats: 0,
-                last_msg_time: SystemTime::now(),
-            }),
-            OrderState::SizeRequested { .. } => Err(Error::OrderWrongState),
-            OrderState::SizeSelected { .. } => Err(Error::OrderWrongState),
-        }
+    pub fn paper_type(&self) -> &str {
+        selection_by_key(&self.selections, "paper")
     }
 
-    pub fn into_order_with_size(self, size: String, price: i32) -> Result<OrderState> {
-        match self {
-            OrderState::RaperRequested { .. } => Err(Error::OrderWrongState),
-            OrderState::SizeRequested {
-                chat_id,
-                customer_name,
-                paper,
-                files,
-                ..
-            } => Ok(OrderState::SizeSelected {
-                chat_id,
-                customer_name,
-                paper,
-                size,
-                price,
-                files,
-                repeats: 0,
-                last_msg_time: SystemTime::now(),
-            }),
-            OrderState::SizeSelected { .. } => Err(Error::OrderWrongState),
-        }
+    pub fn paper_size(&self) -> &str {
+        selection_by_key(&self.selections, "size")
     }
 
     pub fn requested(&mut self) {
+        self.repeats += 1;
+        self.last_msg_time = SystemTime::now();
+    }
+}
+
+fn selection_by_key<'a>(selections: &'a [Selection], key: &str) -> &'a str {
+    selections
+        .iter()
+        .find(|s| s.key.as_deref() == Some(key))
+        .map(|s| s.choice.as_str())
+        .unwrap_or_default()
+}
+
+impl Display for TransitionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is not allowed at stage {}", self.event, self.stage)
+    }
+}
+
+impl Display for Fit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            OrderState::RaperRequested {
-                repeats,
-                last_msg_time,
-                ..
-            } => {
-                *repeats += 1;
-                *last_msg_time = SystemTime::now();
-            }
-            OrderState::SizeRequested {
-                repeats,
-                last_msg_time,
-                ..
-            } => {
-                *repeats += 1;
-                *last_msg_time = SystemTime::now();
-            }
-            OrderState::SizeSelected {
-                repeats,
-                last_msg_time,
-                ..
-            } => {
-                *repeats += 1;
-                *last_msg_time = SystemTime::now();
-            }
+            Fit::Crop => write!(f, "обрезать по формату"),
+            Fit::Fit => write!(f, "вписать с белыми полями"),
         }
     }
 }
 
-impl Display for OrderState {
+impl Display for Order {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            OrderState::RaperRequested { .. } => {
-                unimplemented!()
-            }
-            OrderState::SizeRequested { .. } => {
-                unimplemented!()
-            }
-            OrderState::SizeSelected {
-                chat_id,
-                customer_name,
-                paper,
-                size,
-                files,
-                ..
-            } => {
-                let phone = chat_id.split('@').collect::<Vec<&str>>()[0];
-                write!(
-                    f,
-                    "Телефон: {phone}\nИмя: {}\nТип бумаги: {}\nРазмер: {}\nФайлы: {:?}",
-                    customer_name, paper, size, files
-                )
-            }
+        writeln!(f, "Телефон: {}\nИмя: {}", self.phone(), self.customer_name)?;
+        if let Some(product) = self.product() {
+            writeln!(f, "Товар: {}", product)?;
         }
+        for s in &self.selections {
+            writeln!(f, "{}: {}", s.group, s.choice)?;
+        }
+        if let Some(fit) = self.fit() {
+            writeln!(f, "Кадрирование: {}", fit)?;
+        }
+        if self.payment().is_some() {
+            writeln!(f, "Оплачен: {}", self.is_paid())?;
+        }
+        let urls: Vec<&str> = self.files.iter().map(|f| f.url.as_str()).collect();
+        write!(f, "Файлы: {:?}", urls)
     }
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderOption {
+    pub name: String,
+    pub value: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct OrderMessage {
+    /// Чат заказа с префиксом канала, его сервис печати возвращает в callback
+    pub chat_id: String,
     pub phone: String,
     pub name: String,
+    pub product: String,
+    pub options: Vec<OrderOption>,
     pub paper_type: String,
     pub paper_size: String,
-    pub price: i32,
+    pub fit: Option<Fit>,
+    pub price: Money,
+    pub total: Money,
     pub files: Vec<String>,
+    pub paid: bool,
+    pub payment_id: Option<String>,
+    pub ready_at: Option<DateTime<Utc>>,
 }
 
-impl From<OrderState> for OrderMessage {
-    fn from(order: OrderState) -> Self {
-        match order {
-            OrderState::RaperRequested { .. } => {
-                unreachable!()
-            }
-            OrderState::SizeRequested { .. } => {
-                unreachable!()
-            }
-            OrderState::SizeSelected {
-                chat_id,
-                customer_name,
-                paper,
-                size,
-                price,
-                files,
-                ..
-            } => {
-                let phone = chat_id.split('@').collect::<Vec<&str>>()[0];
-                Self {
-                    phone: phone.to_string(),
-                    name: customer_name,
-                    paper_type: paper,
-                    paper_size: size,
-                    price,
-                    files,
-                }
+impl OrderMessage {
+    /// Ссылки на файлы для получателей вне бота
+    pub fn with_public_urls(mut self) -> Self {
+        self.files = self.files.iter().map(|url| public_url(url)).collect();
+        self
+    }
+}
+
+impl TryFrom<Order> for OrderMessage {
+    type Error = Error;
+
+    fn try_from(order: Order) -> Result<Self, Error> {
+        let (product, price) = match &order.stage {
+            OrderStage::Ready { product, price, .. }
+            | OrderStage::PaymentRequested { product, price, .. } => (product.clone(), *price),
+            stage => {
+                return Err(Error::OrderWrongState(TransitionError {
+                    stage: stage.name(),
+                    event: "Submit",
+                }));
             }
+        };
+        Ok(Self {
+            chat_id: order.chat_id.clone(),
+            phone: order.phone().to_string(),
+            name: order.customer_name.clone(),
+            product,
+            options: order
+                .selections
+                .iter()
+                .map(|s| OrderOption {
+                    name: s.group.clone(),
+                    value: s.choice.clone(),
+                })
+                .collect(),
+            paper_type: order.paper_type().to_string(),
+            paper_size: order.paper_size().to_string(),
+            fit: order.fit(),
+            price,
+            total: price.checked_mul(order.files.len())?,
+            paid: order.is_paid(),
+            payment_id: order.payment_id().map(str::to_string),
+            ready_at: order.ready_at,
+            files: order.files.into_iter().map(|f| f.url).collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stuff::money::Currency;
+
+    fn selection(key: &str, choice: &str) -> Selection {
+        Selection {
+            group: key.to_string(),
+            key: Some(key.to_string()),
+            choice: choice.to_string(),
+            price: Money::new(0, Currency::Rub),
+            fit: None,
         }
     }
+
+    #[test]
+    fn order_transitions() {
+        let price = Money::new(2200, Currency::Rub);
+        let order = Order::new("79146795555@c.us".to_string(), "Andrey".to_string(), vec![]);
+        let order = order
+            .apply(OrderEvent::ProductChosen("Фотопечать".to_string()))
+            .unwrap()
+            .apply(OrderEvent::OptionChosen(selection("paper", "глянцевая")))
+            .unwrap()
+            .apply(OrderEvent::OptionChosen(selection("size", "10x15")))
+            .unwrap();
+        assert!(OrderMessage::try_from(order.clone()).is_err());
+
+        let err = order.clone().apply(OrderEvent::FitChosen(Fit::Crop)).unwrap_err();
+        assert_eq!(err.stage, "OptionsRequested");
+
+        let order = order
+            .apply(OrderEvent::OptionsCompleted {
+                price,
+                fit_required: true,
+                fit: None,
+            })
+            .unwrap()
+            .apply(OrderEvent::FitChosen(Fit::Fit))
+            .unwrap();
+        assert_eq!(order.fit(), Some(Fit::Fit));
+        assert!(!order.to_string().is_empty());
+
+        let message = OrderMessage::try_from(order.clone()).unwrap();
+        assert_eq!(message.paper_size, "10x15");
+        assert_eq!(message.phone, "79146795555");
+        assert_eq!(message.chat_id, order.chat_id);
+
+        let order = order.apply(OrderEvent::Back).unwrap();
+        assert!(matches!(order.stage, OrderStage::FitRequested { .. }));
+        let order = order.apply(OrderEvent::Back).unwrap();
+        assert!(matches!(order.stage, OrderStage::OptionsRequested { .. }));
+        assert_eq!(order.selections.len(), 1);
+        let order = order.apply(OrderEvent::ChangeOption(0)).unwrap();
+        assert!(order.selections.is_empty());
+        assert!(order.clone().apply(OrderEvent::ChangeOption(0)).is_err());
+        let order = order.apply(OrderEvent::Back).unwrap();
+        assert_eq!(order.stage, OrderStage::ProductRequested);
+        assert!(order.apply(OrderEvent::Back).is_err());
+    }
 }
@@ -0,0 +1,82 @@
+use crate::config::config;
+use crate::stuff::error::{Error, Result};
+use lettre::message::header::ContentType;
+use lettre::message::{Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+/// Файл во вложении письма
+pub struct Attachment {
+    pub name: String,
+    pub content_type: &'static str,
+    pub bytes: Vec<u8>,
+}
+
+/// Отправка писем через SMTP из настроек SMTP_*
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    pub fn from_config() -> Result<Mailer> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config().SMTP_HOST)
+            .map_err(|e| Error::EmailInvalid(e.to_string()))?
+            .port(config().SMTP_PORT)
+            .credentials(Credentials::new(
+                config().SMTP_USER.clone(),
+                config().SMTP_PASSWORD.clone(),
+            ))
+            .build();
+        Ok(Mailer {
+            transport,
+            from: mailbox(&config().EMAIL_FROM)?,
+        })
+    }
+
+    pub async fn send(&self, to: &Mailbox, subject: &str, text: &str) -> Result<()> {
+        self.send_with_attachments(to, subject, text, vec![]).await
+    }
+
+    pub async fn send_with_attachments(
+        &self,
+        to: &Mailbox,
+        subject: &str,
+        text: &str,
+        attachments: Vec<Attachment>,
+    ) -> Result<()> {
+        let builder = Message::builder()
+            .from(self.from.clone())
+            .to(to.clone())
+            .subject(subject);
+        let email = if attachments.is_empty() {
+            builder.body(text.to_string())
+        } else {
+            let mut body = MultiPart::mixed().singlepart(SinglePart::plain(text.to_string()));
+            for attachment in attachments {
+                let content_type = ContentType::parse(attachment.content_type)
+                    .map_err(|e| Error::EmailInvalid(e.to_string()))?;
+                body = body.singlepart(
+                    lettre::message::Attachment::new(attachment.name)
+                        .body(attachment.bytes, content_type),
+                );
+            }
+            builder.multipart(body)
+        }
+        .map_err(|e| Error::EmailFailed(e.to_string()))?;
+        self.transport.send(email).await.map_err(|e| {
+            if e.is_permanent() {
+                Error::EmailRejected(e.to_string())
+            } else {
+                Error::EmailFailed(e.to_string())
+            }
+        })?;
+        Ok(())
+    }
+}
+
+pub fn mailbox(address: &str) -> Result<Mailbox> {
+    address
+        .parse()
+        .map_err(|_| Error::EmailInvalid(format!("address {}", address)))
+}
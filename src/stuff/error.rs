@@ -1,5 +1,8 @@
 use std::fmt::{Display, Formatter};
 use std::num::ParseIntError;
+use crate::stuff::data_types::TransitionError;
+use crate::stuff::money::Currency;
+use crate::stuff::worker_api::FieldError;
 use reqwest::StatusCode;
 
 pub type Result<T> = ::std::result::Result<T, Error>;
@@ -9,11 +12,101 @@ pub enum Error {
     Request(reqwest::Error),
     FailedToGetNewMessage(StatusCode, String),
     OrderNotFound(String),
-    PaperInvalid,
-    SizeInvalid(String),
-    OrderWrongState,
+    OptionInvalid,
+    OrderWrongState(TransitionError),
     ParseFailed(ParseIntError),
-    OrderFailed(String)
+    OrderFailed(String),
+    /// Сервис печати не принял заказ из-за ошибок в его параметрах
+    OrderRejected(Vec<FieldError>),
+    /// Сервис печати отказал окончательно, например 4xx без списка ошибок
+    OrderRefused(String),
+    PaymentFailed(String),
+    PaymentNotFound(String),
+    MoneyInvalid(String),
+    /// Сложение сумм в разных валютах
+    CurrencyMismatch(Currency, Currency),
+    ScheduleInvalid(String),
+    CapacityInvalid(String),
+    // -- Green API
+    /// 401/403: неверный токен или инстанс не авторизован
+    ApiUnauthorized(String),
+    /// 466: исчерпан лимит тарифа
+    ApiQuotaExceeded(String),
+    /// 400: запрос отклонен, например слишком длинное сообщение
+    ApiBadRequest(String),
+    /// Чата нет или пользователь заблокировал бота, сообщения в него не доставить
+    ChatUnreachable(String),
+    ApiRateLimited(String),
+    ApiUnavailable(StatusCode, String),
+    ApiFailed(StatusCode, String),
+    /// Уведомление не удалось разобрать
+    MalformedNotification(String),
+    /// Неверное описание канала в CHANNELS
+    ChannelInvalid(String),
+    /// Идентификатор чата не относится ни к одному каналу
+    ChannelNotFound(String),
+    /// Неверные настройки резервного канала оповещений
+    AlertInvalid(String),
+    AlertFailed(String),
+    /// Неверные настройки SMTP или адрес
+    EmailInvalid(String),
+    EmailFailed(String),
+    /// SMTP сервер отклонил письмо окончательно (5xx), например из-за размера
+    EmailRejected(String),
+    /// Заказ не удалось записать в папку станции печати
+    HotFolderFailed(String),
+    /// Неверные WORKER_AUTH или WORKER_SECRET
+    WorkerAuthInvalid(String),
+    /// Запрос не подписан или подпись не совпала
+    SignatureInvalid(String),
+    /// Журнал переписки недоступен
+    AuditFailed(String),
+    /// Неверное REPORT_TIME или период отчета
+    ReportInvalid(String),
+}
+
+impl Error {
+    /// Ошибка ответа Green API по коду статуса
+    pub fn from_api_status(status: StatusCode, body: String) -> Error {
+        match status.as_u16() {
+            401 | 403 => Error::ApiUnauthorized(body),
+            466 => Error::ApiQuotaExceeded(body),
+            400 => Error::ApiBadRequest(body),
+            429 => Error::ApiRateLimited(body),
+            _ if status.is_server_error() => Error::ApiUnavailable(status, body),
+            _ => Error::ApiFailed(status, body),
+        }
+    }
+
+    /// Ошибка временная и запрос стоит повторить позже
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::ApiRateLimited(_) | Error::ApiUnavailable(..) => true,
+            Error::Request(e) => e.is_timeout() || e.is_connect(),
+            _ => false,
+        }
+    }
+
+    /// Заказ не передать и повтором: получатель отказал окончательно или заказ некорректен
+    pub fn is_refusal(&self) -> bool {
+        matches!(
+            self,
+            Error::OrderRefused(_)
+                | Error::EmailRejected(_)
+                | Error::EmailInvalid(_)
+                | Error::MoneyInvalid(_)
+                | Error::OrderWrongState(_)
+        )
+    }
+
+    pub fn is_rate_limited(&self) -> bool {
+        matches!(self, Error::ApiRateLimited(_))
+    }
+
+    /// Ошибка касается всего инстанса, а не отдельного чата
+    pub fn is_instance_failure(&self) -> bool {
+        matches!(self, Error::ApiUnauthorized(_) | Error::ApiQuotaExceeded(_))
+    }
 }
 
 // region:    ---From
@@ -23,6 +116,12 @@ impl From<ParseIntError> for Error {
     }
 }
 
+impl From<TransitionError> for Error {
+    fn from(err: TransitionError) -> Self {
+        Error::OrderWrongState(err)
+    }
+}
+
 impl From<reqwest::Error> for Error {
     fn from(err: reqwest::Error) -> Self {
         Error::Request(err)
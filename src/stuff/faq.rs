@@ -0,0 +1,55 @@
+use crate::config::config;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::Read;
+
+/// Ответ на частый вопрос. Вопрос распознается, если в тексте встречается
+/// одно из ключевых слов. В ответе можно использовать подстановки
+/// {address}, {phone}, {prices} и {hours} - часы работы из SHOP_HOURS.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FaqEntry {
+    pub keywords: Vec<String>,
+    pub answer: String,
+}
+
+pub struct Faq {
+    entries: Vec<FaqEntry>,
+}
+
+impl Faq {
+    pub fn new() -> Self {
+        Faq::load_from_file(&config().FAQ_FILE)
+    }
+
+    fn load_from_file(path: &str) -> Faq {
+        let mut file = File::open(path).expect("FAQ file not found");
+        let mut buffer = String::new();
+        file.read_to_string(&mut buffer)
+            .expect("FAQ file read error");
+        let entries =
+            serde_json::from_str::<Vec<FaqEntry>>(&buffer).expect("FAQ file decode error");
+        Faq { entries }
+    }
+
+    pub fn find(&self, text: &str) -> Option<&FaqEntry> {
+        let text = text.to_lowercase();
+        self.entries.iter().find(|e| {
+            e.keywords
+                .iter()
+                .any(|k| text.contains(k.to_lowercase().as_str()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_test() {
+        let faq = Faq::new();
+        assert!(faq.find("Здравствуйте, сколько стоит?").is_some());
+        assert!(faq.find("Когда работаете?").is_some());
+        assert!(faq.find("1").is_none());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::config::config;
+use crate::stuff::error::{Error, Result};
+use crate::stuff::telegram::{FILE_SCHEME, TelegramFiles};
+
+/// Файлы заказов. Файлы Telegram скачиваются только внутри бота,
+/// получателям вне процесса отдается ссылка на прокси бота `/files`.
+#[derive(Clone)]
+pub struct FileStore {
+    client: reqwest::Client,
+    /// Боты по имени канала, у единственного бота без CHANNELS имя пустое
+    telegram: Vec<(String, TelegramFiles)>,
+}
+
+impl FileStore {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            telegram: vec![],
+        }
+    }
+
+    pub fn with_telegram(mut self, channel: &str, files: TelegramFiles) -> Self {
+        self.telegram.push((channel.to_string(), files));
+        self
+    }
+
+    /// Содержимое файла по ссылке из заказа
+    pub async fn download(&self, url: &str) -> Result<Vec<u8>> {
+        if let Some(reference) = url.strip_prefix(FILE_SCHEME) {
+            return self.telegram_file(reference).await;
+        }
+        let bytes = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(bytes.to_vec())
+    }
+
+    /// Файл бота по "канал:file_id" или "file_id"
+    pub async fn telegram_file(&self, reference: &str) -> Result<Vec<u8>> {
+        let (channel, file_id) = reference.rsplit_once(':').unwrap_or(("", reference));
+        let (_, files) = self
+            .telegram
+            .iter()
+            .find(|(name, _)| name == channel)
+            .ok_or_else(|| Error::ChannelNotFound(reference.to_string()))?;
+        files.download(file_id).await
+    }
+}
+
+/// Ссылка на файл для получателей вне бота.
+/// Файлы Telegram доступны через FILES_PUBLIC_URL, без него остается ссылка бота без токена
+pub fn public_url(url: &str) -> String {
+    let base = config().FILES_PUBLIC_URL.trim_end_matches('/');
+    match url.strip_prefix(FILE_SCHEME) {
+        Some(reference) if !base.is_empty() => format!("{}/{}", base, reference),
+        _ => url.to_string(),
+    }
+}
+
+/// MIME тип и расширение изображения по первым байтам
+pub fn image_type(bytes: &[u8]) -> Option<(&'static str, &'static str)> {
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        Some(("image/jpeg", "jpg"))
+    } else if bytes.starts_with(b"\x89PNG") {
+        Some(("image/png", "png"))
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        Some(("image/webp", "webp"))
+    } else if bytes.get(4..12) == Some(b"ftypheic") {
+        Some(("image/heic", "heic"))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn public_url_test() {
+        assert_eq!(
+            public_url("telegram:shop:AgAD"),
+            "http://localhost:8080/files/shop:AgAD"
+        );
+        assert_eq!(
+            public_url("https://example.com/1.jpg"),
+            "https://example.com/1.jpg"
+        );
+        assert_eq!(image_type(&[0xFF, 0xD8, 0xFF]), Some(("image/jpeg", "jpg")));
+        assert_eq!(image_type(b"text"), None);
+    }
+}
@@ -0,0 +1,254 @@
+use crate::config::config;
+use crate::stuff::alert::Alerter;
+use crate::stuff::data_types::InstanceState;
+use crate::stuff::error::Result;
+use crate::stuff::transport::Transport;
+use log::{error, info, warn};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Сколько проверок подряд должно завершиться ошибкой, чтобы сообщить о недоступности
+const FAILURE_ALERT_THRESHOLD: u32 = 3;
+const MAX_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+/// Периодически проверяет состояние каналов и оповещает администратора
+/// по резервному каналу, если канал отключен или не отвечает.
+pub struct HealthMonitor<'a, T: Transport> {
+    transport: &'a T,
+    alerter: Alerter,
+    interval: Duration,
+    channels: HashMap<String, ChannelHealth>,
+}
+
+/// Последнее состояние канала и число ошибок проверки подряд
+#[derive(Default)]
+struct ChannelHealth {
+    state: Option<InstanceState>,
+    failures: u32,
+}
+
+impl<'a, T: Transport> HealthMonitor<'a, T> {
+    pub fn new(transport: &'a T, alerter: Alerter) -> Self {
+        Self {
+            transport,
+            alerter,
+            interval: Duration::from_secs(config().HEALTH_CHECK_INTERVAL),
+            channels: HashMap::new(),
+        }
+    }
+
+    pub async fn run(mut self) {
+        loop {
+            let delay = self.check().await;
+            sleep(delay).await;
+        }
+    }
+
+    /// Одна проверка всех каналов, возвращает задержку до следующей
+    async fn check(&mut self) -> Duration {
+        let mut delay = self.interval;
+        for (name, state) in self.transport.channel_states().await {
+            let label = channel_label(&name);
+            let health = self.channels.entry(name).or_default();
+            let channel_delay = health
+                .check(&self.alerter, &label, state, self.interval)
+                .await;
+            delay = delay.min(channel_delay);
+        }
+        delay
+    }
+}
+
+/// Имя канала для оповещений, у единственного канала - мессенджер из MESSENGER
+pub fn channel_label(name: &str) -> String {
+    match name {
+        "" => config().MESSENGER.clone(),
+        name => name.to_string(),
+    }
+}
+
+impl ChannelHealth {
+    async fn check(
+        &mut self,
+        alerter: &Alerter,
+        label: &str,
+        state: Result<InstanceState>,
+        interval: Duration,
+    ) -> Duration {
+        match state {
+            Ok(state) => {
+                if self.failures >= FAILURE_ALERT_THRESHOLD {
+                    alerter
+                        .alert(
+                            &format!("Канал {} доступен", label),
+                            &format!("Связь с каналом {} восстановлена", label),
+                        )
+                        .await;
+                }
+                self.failures = 0;
+                self.state_changed(alerter, label, state).await;
+                interval
+            }
+            Err(e) => {
+                self.failures += 1;
+                let delay = backoff(interval.min(MAX_BACKOFF), self.failures);
+                warn!(
+                    "[health] channel {} check failed {} times, next in {:?}: {}",
+                    label, self.failures, delay, e
+                );
+                if self.failures == FAILURE_ALERT_THRESHOLD {
+                    alerter
+                        .alert(
+                            &format!("Канал {} недоступен", label),
+                            &format!("Не удается получить состояние канала {}: {}", label, e),
+                        )
+                        .await;
+                }
+                delay
+            }
+        }
+    }
+
+    async fn state_changed(&mut self, alerter: &Alerter, label: &str, state: InstanceState) {
+        let previous = self.state.replace(state);
+        if previous == Some(state) {
+            return;
+        }
+        match state {
+            InstanceState::Authorized => {
+                info!("[health] channel {} authorized", label);
+                if previous.is_some() {
+                    alerter
+                        .alert(
+                            &format!("Канал {} подключен", label),
+                            &format!(
+                                "Канал {} снова авторизован, прием заказов возобновлен",
+                                label
+                            ),
+                        )
+                        .await;
+                }
+            }
+            InstanceState::NotAuthorized | InstanceState::Blocked | InstanceState::YellowCard => {
+                error!("[health] channel {} state {:?}", label, state);
+                alerter
+                    .alert(
+                        &format!("Канал {} отключен", label),
+                        &format!(
+                            "Канал {} в состоянии {:?}, заказы через него не принимаются. Авторизуйте инстанс по QR-коду в личном кабинете Green API или проверьте токен бота Telegram",
+                            label, state
+                        ),
+                    )
+                    .await;
+            }
+            InstanceState::Starting | InstanceState::SleepMode | InstanceState::Other => {
+                warn!("[health] channel {} state {:?}", label, state);
+            }
+        }
+    }
+}
+
+/// Пауза после `failures` ошибок подряд: от секунды с удвоением, не больше `max`
+pub fn backoff(max: Duration, failures: u32) -> Duration {
+    let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+    Duration::from_secs(1).saturating_mul(factor).min(max)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stuff::data_types::Message;
+    use crate::stuff::error::Error;
+    use crate::stuff::multi::MultiTransport;
+    use std::collections::VecDeque;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+
+    /// Отвечает заранее заданными состояниями
+    struct ScriptedTransport(Mutex<VecDeque<Result<InstanceState>>>);
+
+    impl Transport for ScriptedTransport {
+        async fn receive_message(&self) -> Result<Message> {
+            Ok(Message::Empty)
+        }
+
+        async fn send_message(&self, _chat_id: String, _msg: String) -> Result<()> {
+            Ok(())
+        }
+
+        async fn instance_state(&self) -> Result<InstanceState> {
+            self.0.lock().unwrap().pop_front().unwrap()
+        }
+    }
+
+    #[test]
+    fn backoff_test() {
+        let max = Duration::from_secs(60);
+        assert_eq!(backoff(max, 1), Duration::from_secs(1));
+        assert_eq!(backoff(max, 3), Duration::from_secs(4));
+        assert_eq!(backoff(max, 100), max);
+    }
+
+    #[tokio::test]
+    async fn alert_on_logout_and_resume() {
+        let path = std::env::temp_dir().join("astrafoto-health-test.log");
+        let _ = fs::remove_file(&path);
+        let unavailable = || {
+            Err(Error::ApiUnavailable(
+                reqwest::StatusCode::BAD_GATEWAY,
+                String::new(),
+            ))
+        };
+        let transport = ScriptedTransport(Mutex::new(VecDeque::from([
+            Ok(InstanceState::Authorized),
+            Ok(InstanceState::NotAuthorized),
+            Ok(InstanceState::NotAuthorized),
+            unavailable(),
+            unavailable(),
+            unavailable(),
+            Ok(InstanceState::Authorized),
+        ])));
+        let mut monitor = HealthMonitor::new(&transport, Alerter::LogFile(PathBuf::from(&path)));
+
+        let mut delays = Vec::new();
+        for _ in 0..7 {
+            delays.push(monitor.check().await.as_secs());
+        }
+        assert_eq!(delays, [60, 60, 60, 1, 2, 4, 60]);
+
+        let log = fs::read_to_string(&path).unwrap();
+        let subjects: Vec<&str> = log
+            .lines()
+            .filter_map(|l| l.split_once('[')?.1.split_once(']'))
+            .map(|(subject, _)| subject)
+            .collect();
+        assert_eq!(
+            subjects,
+            [
+                "Канал whatsapp отключен",
+                "Канал whatsapp недоступен",
+                "Канал whatsapp доступен",
+                "Канал whatsapp подключен"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn alert_names_channel() {
+        let path = std::env::temp_dir().join("astrafoto-health-channels-test.log");
+        let _ = fs::remove_file(&path);
+        let scripted = |state| ScriptedTransport(Mutex::new(VecDeque::from([Ok(state)])));
+        let transport = MultiTransport::new(vec![
+            ("wa".to_string(), scripted(InstanceState::Authorized)),
+            ("tg".to_string(), scripted(InstanceState::NotAuthorized)),
+        ]);
+        let mut monitor = HealthMonitor::new(&transport, Alerter::LogFile(PathBuf::from(&path)));
+        monitor.check().await;
+
+        let log = fs::read_to_string(&path).unwrap();
+        assert_eq!(log.lines().count(), 1);
+        assert!(log.contains("[Канал tg отключен]"));
+    }
+}
@@ -0,0 +1,331 @@
+use crate::config::config;
+use crate::stuff::data_types::{Fit, Order, OrderMessage};
+use crate::stuff::error::{Error, Result};
+use crate::stuff::files::{FileStore, public_url};
+use crate::stuff::money::Money;
+use crate::stuff::sink::{OrderSink, Submitted, local_order_id};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const IMAGE_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "heic", "webp"];
+/// Фото печатается в одном экземпляре
+const COPIES: u32 = 1;
+
+/// Формат описания заказа для станции печати
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ManifestFormat {
+    /// order.json
+    Json,
+    /// MISC/AUTPRINT.MRK в формате DPOF
+    Dpof,
+}
+
+impl ManifestFormat {
+    /// Форматы через запятую: "json", "dpof" или "json,dpof"
+    pub fn parse_list(formats: &str) -> Result<Vec<ManifestFormat>> {
+        formats
+            .split(',')
+            .map(str::trim)
+            .filter(|f| !f.is_empty())
+            .map(|f| match f {
+                "json" => Ok(ManifestFormat::Json),
+                "dpof" => Ok(ManifestFormat::Dpof),
+                _ => Err(Error::HotFolderFailed(format!("manifest format {}", f))),
+            })
+            .collect()
+    }
+}
+
+/// Папка, которую отслеживает станция печати. Заказ собирается в папке
+/// подготовки и целиком переносится в горячую папку, поэтому станция
+/// никогда не видит заказ без части файлов.
+pub struct HotFolder {
+    files: FileStore,
+    dir: PathBuf,
+    /// Должна быть на том же диске, что и `dir`, чтобы перенос был атомарным
+    staging_dir: PathBuf,
+    formats: Vec<ManifestFormat>,
+}
+
+/// Описание заказа для order.json
+#[derive(Serialize)]
+struct Manifest<'a> {
+    id: &'a str,
+    customer: &'a str,
+    phone: &'a str,
+    product: &'a str,
+    paper: &'a str,
+    size: &'a str,
+    fit: Option<Fit>,
+    total: Money,
+    paid: bool,
+    ready_at: Option<DateTime<Utc>>,
+    files: Vec<ManifestFile>,
+}
+
+#[derive(Serialize)]
+struct ManifestFile {
+    file: String,
+    url: String,
+    copies: u32,
+}
+
+impl HotFolder {
+    pub fn new(files: FileStore) -> Result<Self> {
+        Ok(Self {
+            files,
+            dir: PathBuf::from(&config().HOT_FOLDER_DIR),
+            staging_dir: PathBuf::from(&config().HOT_FOLDER_STAGING_DIR),
+            formats: ManifestFormat::parse_list(&config().HOT_FOLDER_MANIFEST)?,
+        })
+    }
+
+    async fn download(&self, url: &str, path: &Path) -> Result<()> {
+        let bytes = self.files.download(url).await?;
+        fs::write(path, bytes).map_err(io_error)
+    }
+
+    /// Скачивает файлы и пишет описание заказа в папку подготовки
+    async fn prepare(&self, dir: &Path, id: &str, message: &OrderMessage) -> Result<()> {
+        fs::create_dir_all(dir).map_err(io_error)?;
+        let mut files = vec![];
+        for (idx, url) in message.files.iter().enumerate() {
+            let file = file_name(idx, url);
+            self.download(url, &dir.join(&file)).await?;
+            files.push(ManifestFile {
+                file,
+                url: public_url(url),
+                copies: COPIES,
+            });
+        }
+        let manifest = Manifest {
+            id,
+            customer: &message.name,
+            phone: &message.phone,
+            product: &message.product,
+            paper: &message.paper_type,
+            size: &message.paper_size,
+            fit: message.fit,
+            total: message.total,
+            paid: message.paid,
+            ready_at: message.ready_at,
+            files,
+        };
+        for format in &self.formats {
+            match format {
+                ManifestFormat::Json => {
+                    let json = serde_json::to_vec_pretty(&manifest)
+                        .map_err(|e| Error::HotFolderFailed(e.to_string()))?;
+                    fs::write(dir.join("order.json"), json).map_err(io_error)?;
+                }
+                ManifestFormat::Dpof => {
+                    let misc = dir.join("MISC");
+                    fs::create_dir_all(&misc).map_err(io_error)?;
+                    fs::write(misc.join("AUTPRINT.MRK"), dpof(&manifest, Utc::now()))
+                        .map_err(io_error)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OrderSink for HotFolder {
+    async fn submit(&self, order: Order) -> Result<Submitted> {
+        let id = local_order_id(&order, Utc::now());
+        let message = OrderMessage::try_from(order)?;
+        let staging = self.staging_dir.join(&id);
+        let target = self.dir.join(&id);
+        if target.exists() {
+            return Err(Error::HotFolderFailed(format!("{} already exists", id)));
+        }
+        if let Err(e) = self.prepare(&staging, &id, &message).await {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+        fs::create_dir_all(&self.dir).map_err(io_error)?;
+        fs::rename(&staging, &target).map_err(io_error)?;
+        Ok(Submitted::local(id))
+    }
+}
+
+fn io_error(e: std::io::Error) -> Error {
+    Error::HotFolderFailed(e.to_string())
+}
+
+/// "001.jpg": номер по порядку и расширение из ссылки
+fn file_name(idx: usize, url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let ext = path
+        .rsplit('/')
+        .next()
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, ext)| ext.to_lowercase())
+        .filter(|ext| IMAGE_EXTENSIONS.contains(&ext.as_str()))
+        .unwrap_or_else(|| "jpg".to_string());
+    format!("{:03}.{}", idx + 1, ext)
+}
+
+/// Файл заказа печати DPOF. Параметры печати, которых нет в стандарте,
+/// записываются в раздел производителя VUQ.
+fn dpof(manifest: &Manifest, now: DateTime<Utc>) -> String {
+    let mut output = String::new();
+    let _ = writeln!(output, "[HDR]");
+    let _ = writeln!(output, "GEN REV = 01.10");
+    let _ = writeln!(
+        output,
+        "GEN CRT = \"astrafoto-bot\" -{}",
+        env!("CARGO_PKG_VERSION")
+    );
+    let _ = writeln!(output, "GEN DTM = {}", now.format("%Y:%m:%d:%H:%M:%S"));
+    let _ = writeln!(output, "USR NAM = \"{}\"", dpof_text(manifest.customer));
+    let _ = writeln!(output, "USR TEL = \"{}\"", dpof_text(manifest.phone));
+    let _ = writeln!(output, "VUQ RGN = BGN");
+    let _ = writeln!(output, "VUQ VNM = \"astrafoto\" -ATR \"order\"");
+    let _ = writeln!(output, "VUQ VER = 01.00");
+    let _ = writeln!(output, "PRT ORD = \"{}\"", dpof_text(manifest.id));
+    let _ = writeln!(output, "PRT PAP = \"{}\"", dpof_text(manifest.paper));
+    let _ = writeln!(output, "PRT PSZ = \"{}\"", dpof_text(manifest.size));
+    if let Some(fit) = manifest.fit {
+        let fit = match fit {
+            Fit::Crop => "CROP",
+            Fit::Fit => "FIT",
+        };
+        let _ = writeln!(output, "PRT FIT = {}", fit);
+    }
+    let _ = writeln!(output, "VUQ RGN = END");
+    for (idx, file) in manifest.files.iter().enumerate() {
+        let _ = writeln!(output);
+        let _ = writeln!(output, "[JOB]");
+        let _ = writeln!(output, "PRT PID = {:03}", idx + 1);
+        let _ = writeln!(output, "PRT TYP = STD");
+        let _ = writeln!(output, "PRT QTY = {:03}", file.copies);
+        let _ = writeln!(output, "IMG FMT = EXIF2 -J");
+        let _ = writeln!(output, "<IMG SRC = \"../{}\">", file.file);
+    }
+    output.replace('\n', "\r\n")
+}
+
+/// Кавычки и переводы строк ломают строку DPOF
+fn dpof_text(text: &str) -> String {
+    text.chars()
+        .map(|c| if c == '"' || c.is_control() { ' ' } else { c })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stuff::catalog::Selection;
+    use crate::stuff::data_types::{OrderEvent, OrderFile};
+    use crate::stuff::money::Currency;
+    use axum::Router;
+    use axum::routing::get;
+
+    fn selection(key: &str, choice: &str) -> Selection {
+        Selection {
+            group: key.to_string(),
+            key: Some(key.to_string()),
+            choice: choice.to_string(),
+            price: Money::new(0, Currency::Rub),
+            fit: None,
+        }
+    }
+
+    #[test]
+    fn file_name_test() {
+        assert_eq!(file_name(0, "http://host/a/photo.PNG?token=1"), "001.png");
+        assert_eq!(file_name(11, "http://host/file/abc"), "012.jpg");
+    }
+
+    #[test]
+    fn parse_formats_test() {
+        assert_eq!(
+            ManifestFormat::parse_list("json, dpof").unwrap(),
+            [ManifestFormat::Json, ManifestFormat::Dpof]
+        );
+        assert!(ManifestFormat::parse_list("xml").is_err());
+    }
+
+    #[tokio::test]
+    async fn submit_test() {
+        let app = Router::new()
+            .route("/photos/{name}", get(|| async { "jpeg bytes" }))
+            .route(
+                "/missing/{name}",
+                get(|| async { axum::http::StatusCode::NOT_FOUND }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let order = |chat_id: &str, paths: &[&str]| {
+            let files = paths
+                .iter()
+                .map(|path| OrderFile {
+                    url: format!("http://{}/{}", addr, path),
+                    dimensions: None,
+                })
+                .collect();
+            Order::new(chat_id.to_string(), "Andrey".to_string(), files)
+                .apply(OrderEvent::ProductChosen("Фотопечать".to_string()))
+                .unwrap()
+                .apply(OrderEvent::OptionChosen(selection("paper", "глянцевая")))
+                .unwrap()
+                .apply(OrderEvent::OptionChosen(selection("size", "10x15")))
+                .unwrap()
+                .apply(OrderEvent::OptionsCompleted {
+                    price: Money::new(2200, Currency::Rub),
+                    fit_required: false,
+                    fit: None,
+                })
+                .unwrap()
+        };
+        let root = std::env::temp_dir().join("astrafoto-hotfolder-test");
+        let _ = fs::remove_dir_all(&root);
+        let sink = HotFolder {
+            files: FileStore::new(reqwest::Client::new()),
+            dir: root.join("hot"),
+            staging_dir: root.join("staging"),
+            formats: vec![ManifestFormat::Json, ManifestFormat::Dpof],
+        };
+
+        let id = sink
+            .submit(order("79146795555@c.us", &["photos/1.jpg", "photos/2.png"]))
+            .await
+            .unwrap()
+            .order_id;
+        assert!(id.ends_with("-5555"));
+        let order_dir = root.join("hot").join(&id);
+        assert_eq!(fs::read(order_dir.join("002.png")).unwrap(), b"jpeg bytes");
+        assert!(!root.join("staging").join(&id).exists());
+        let json: serde_json::Value =
+            serde_json::from_slice(&fs::read(order_dir.join("order.json")).unwrap()).unwrap();
+        assert_eq!(json["id"], id.as_str());
+        assert_eq!(json["paper"], "глянцевая");
+        assert_eq!(json["size"], "10x15");
+        assert_eq!(json["phone"], "79146795555");
+        assert_eq!(json["files"][1]["file"], "002.png");
+        assert_eq!(json["files"][1]["copies"], 1);
+        let dpof = fs::read_to_string(order_dir.join("MISC/AUTPRINT.MRK")).unwrap();
+        assert!(dpof.contains("USR NAM = \"Andrey\"\r\n"));
+        assert!(dpof.contains("PRT PSZ = \"10x15\""));
+        assert!(dpof.contains("<IMG SRC = \"../002.png\">"));
+
+        // Заказ с недоступным файлом не попадает в горячую папку
+        let failed = sink
+            .submit(order(
+                "79140001111@c.us",
+                &["photos/1.jpg", "missing/2.jpg"],
+            ))
+            .await;
+        assert!(failed.is_err());
+        let hot: Vec<_> = fs::read_dir(root.join("hot")).unwrap().collect();
+        assert_eq!(hot.len(), 1);
+        assert_eq!(fs::read_dir(root.join("staging")).unwrap().count(), 0);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
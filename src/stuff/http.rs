@@ -0,0 +1,36 @@
+use crate::config::config;
+use crate::stuff::error::Result;
+use reqwest::{Client, Proxy};
+use std::time::Duration;
+
+/// Общий HTTP клиент для Green API, обработчика заказов и платежной системы.
+/// Клиент держит пул соединений, поэтому создается один раз и клонируется.
+pub fn build_client() -> Result<Client> {
+    build_client_with_proxy(&config().HTTP_PROXY)
+}
+
+/// `proxy` - http://, https://, socks5:// или socks5h://, пустая строка без прокси
+fn build_client_with_proxy(proxy: &str) -> Result<Client> {
+    let mut builder = Client::builder()
+        .user_agent(config().HTTP_USER_AGENT.as_str())
+        .connect_timeout(Duration::from_secs(config().HTTP_CONNECT_TIMEOUT))
+        .timeout(Duration::from_secs(config().HTTP_TIMEOUT))
+        .pool_idle_timeout(Duration::from_secs(90))
+        .tcp_keepalive(Duration::from_secs(60));
+    if !proxy.is_empty() {
+        builder = builder.proxy(Proxy::all(proxy)?);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn build_client_test() {
+        assert!(build_client().is_ok());
+        assert!(build_client_with_proxy("socks5://127.0.0.1:1080").is_ok());
+        assert!(build_client_with_proxy("http://127.0.0.1:3128").is_ok());
+    }
+}
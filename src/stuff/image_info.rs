@@ -0,0 +1,126 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+/// Допустимое расхождение пропорций фото и формата печати
+const RATIO_TOLERANCE: f64 = 0.03;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Dimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Dimensions {
+    /// Отношение длинной стороны к короткой, ориентация не учитывается
+    pub fn ratio(&self) -> Option<f64> {
+        let (long, short) = (self.width.max(self.height), self.width.min(self.height));
+        if short == 0 {
+            None
+        } else {
+            Some(long as f64 / short as f64)
+        }
+    }
+}
+
+/// Размеры изображения по превью в base64, которое присылает WhatsApp.
+/// Превью уменьшено пропорционально, поэтому подходит для оценки соотношения сторон.
+pub fn thumbnail_dimensions(thumbnail: &str) -> Option<Dimensions> {
+    let bytes = STANDARD.decode(thumbnail.trim()).ok()?;
+    jpeg_dimensions(&bytes)
+}
+
+/// Ищет в JPEG маркер SOF и читает из него высоту и ширину
+pub fn jpeg_dimensions(bytes: &[u8]) -> Option<Dimensions> {
+    if bytes.get(..2)? != [0xFF, 0xD8] {
+        return None;
+    }
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() {
+        if bytes[pos] != 0xFF {
+            return None;
+        }
+        let marker = bytes[pos + 1];
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        let len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let is_sof = matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+        if is_sof {
+            let sof = bytes.get(pos + 4..pos + 9)?;
+            return Some(Dimensions {
+                height: u16::from_be_bytes([sof[1], sof[2]]) as u32,
+                width: u16::from_be_bytes([sof[3], sof[4]]) as u32,
+            });
+        }
+        pos += 2 + len;
+    }
+    None
+}
+
+/// Соотношение сторон формата печати по названию размера: "10x15", "Полароид 10х8,5"
+pub fn print_ratio(size: &str) -> Option<f64> {
+    let normalized = size.to_lowercase().replace(',', ".").replace('х', "x");
+    normalized.split_whitespace().find_map(|part| {
+        let (a, b) = part.split_once('x')?;
+        let (a, b) = (a.parse::<f64>().ok()?, b.parse::<f64>().ok()?);
+        if a <= 0.0 || b <= 0.0 {
+            None
+        } else {
+            Some(a.max(b) / a.min(b))
+        }
+    })
+}
+
+pub fn ratio_matches(a: f64, b: f64) -> bool {
+    (a - b).abs() / b <= RATIO_TOLERANCE
+}
+
+/// Отношение в привычном виде: 1.5 -> "3:2", 1.333 -> "4:3"
+pub fn format_ratio(ratio: f64) -> String {
+    (1..=10)
+        .map(|den| (den, (ratio * den as f64).round() as u32))
+        .find(|(den, num)| (*num as f64 / *den as f64 - ratio).abs() / ratio <= 0.01)
+        .map(|(den, num)| format!("{}:{}", num, den))
+        .unwrap_or_else(|| format!("{:.2}:1", ratio))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jpeg_sof_test() {
+        let jpeg = [
+            0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xC0, 0x00, 0x11, 0x08, 0x00,
+            0x48, 0x00, 0x60, 0x03,
+        ];
+        assert_eq!(
+            jpeg_dimensions(&jpeg),
+            Some(Dimensions {
+                width: 96,
+                height: 72
+            })
+        );
+        assert_eq!(jpeg_dimensions(b"not a jpeg"), None);
+        assert_eq!(
+            thumbnail_dimensions(&STANDARD.encode(jpeg)).unwrap().width,
+            96
+        );
+    }
+
+    #[test]
+    fn print_ratio_test() {
+        assert_eq!(print_ratio("10x15"), Some(1.5));
+        assert_eq!(print_ratio("Полароид 10х10"), Some(1.0));
+        assert!(ratio_matches(print_ratio("Полароид 10х8,5").unwrap(), 10.0 / 8.5));
+        assert_eq!(print_ratio("A4"), None);
+    }
+
+    #[test]
+    fn format_ratio_test() {
+        assert_eq!(format_ratio(1.5), "3:2");
+        assert_eq!(format_ratio(4032.0 / 3024.0), "4:3");
+        assert_eq!(format_ratio(2340.0 / 1080.0), "13:6");
+    }
+}
@@ -0,0 +1,200 @@
+use crate::config::config;
+use crate::error::{Error, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::Record;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::io::Write;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+tokio::task_local! {
+    static CONTEXT: RefCell<LogContext>;
+}
+
+/// Идентификаторы, которые добавляются ко всем записям внутри [`scope`]
+#[derive(Debug, Clone, Default)]
+struct LogContext {
+    message_id: Option<String>,
+    order_id: Option<String>,
+}
+
+/// Уровни по модулям задаются RUST_LOG, например
+/// "info,astrafoto_bot::stuff::transport=debug"
+pub fn init() -> Result<()> {
+    let json = match config().LOG_FORMAT.as_str() {
+        "text" => false,
+        "json" => true,
+        _ => return Err(Error::ConfigWrongFormat("LOG_FORMAT")),
+    };
+    let redacted = config().LOG_REDACT;
+    env_logger::Builder::new()
+        .parse_filters(&config().RUST_LOG)
+        .format(move |buf, record| {
+            let line = format_record(record, Utc::now(), json, redacted);
+            writeln!(buf, "{}", line)
+        })
+        .init();
+    Ok(())
+}
+
+/// Новый идентификатор для сообщения или заказа, уникален в пределах нескольких лет
+pub fn new_id() -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    let counter = COUNTER.fetch_add(1, Ordering::Relaxed) & 0xffff;
+    format!("{:x}{:04x}", Utc::now().timestamp_millis(), counter)
+}
+
+/// Записи, сделанные при выполнении `f`, помечаются `message_id`
+pub async fn scope<F: Future>(message_id: Option<String>, f: F) -> F::Output {
+    let context = LogContext {
+        message_id,
+        order_id: None,
+    };
+    CONTEXT.scope(RefCell::new(context), f).await
+}
+
+/// Последующие записи текущего [`scope`] относятся к заказу `order_id`
+pub fn set_order(order_id: &str) {
+    let _ = CONTEXT.try_with(|c| c.borrow_mut().order_id = Some(order_id.to_string()));
+}
+
+fn format_record(record: &Record, now: DateTime<Utc>, json: bool, redacted: bool) -> String {
+    let context = CONTEXT.try_with(|c| c.borrow().clone()).unwrap_or_default();
+    let message = record.args().to_string();
+    let message = match redacted {
+        true => redact(&message),
+        false => message,
+    };
+    let time = now.to_rfc3339_opts(SecondsFormat::Millis, true);
+    if json {
+        let mut fields = Map::new();
+        fields.insert("time".to_string(), Value::from(time));
+        fields.insert("level".to_string(), Value::from(record.level().as_str()));
+        fields.insert("target".to_string(), Value::from(record.target()));
+        fields.insert("message".to_string(), Value::from(message));
+        if let Some(id) = context.message_id {
+            fields.insert("message_id".to_string(), Value::from(id));
+        }
+        if let Some(id) = context.order_id {
+            fields.insert("order_id".to_string(), Value::from(id));
+        }
+        return Value::Object(fields).to_string();
+    }
+    let mut line = format!(
+        "{} {:<5} {} > {}",
+        time,
+        record.level(),
+        record.target(),
+        message
+    );
+    if let Some(id) = context.message_id {
+        line.push_str(&format!(" message_id={}", id));
+    }
+    if let Some(id) = context.order_id {
+        line.push_str(&format!(" order_id={}", id));
+    }
+    line
+}
+
+/// Скрывает номера телефонов (7 и больше цифр подряд, кроме последних 4)
+/// и пути в ссылках на файлы, хост остается для разбора ошибок
+pub fn redact(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut idx = 0;
+    while let Some(rest) = text.get(idx..).filter(|r| !r.is_empty()) {
+        if rest.starts_with("http://") || rest.starts_with("https://") {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>'))
+                .unwrap_or(rest.len());
+            output.push_str(&redact_url(&rest[..end]));
+            idx += end;
+        } else if rest.starts_with(|c: char| c.is_ascii_digit()) {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let digits = &rest[..end];
+            if digits.len() >= 7 {
+                output.push_str("***");
+                output.push_str(&digits[digits.len() - 4..]);
+            } else {
+                output.push_str(digits);
+            }
+            idx += end;
+        } else {
+            let c = rest.chars().next().unwrap_or_default();
+            output.push(c);
+            idx += c.len_utf8();
+        }
+    }
+    output
+}
+
+fn redact_url(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    match rest.find(['/', '?']) {
+        Some(end) => format!("{}://{}/***", scheme, &rest[..end]),
+        None => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn redact_test() {
+        assert_eq!(
+            redact("Order from 79146795555@c.us DONE with id 2025-15"),
+            "Order from ***5555@c.us DONE with id 2025-15"
+        );
+        assert_eq!(
+            redact("file https://api.green-api.com/file/abc.jpg?token=1 failed"),
+            "file https://api.green-api.com/*** failed"
+        );
+        assert_eq!(redact("Привет, http://host"), "Привет, http://host");
+    }
+
+    #[tokio::test]
+    async fn format_record_test() {
+        let now = DateTime::parse_from_rfc3339("2026-10-19T10:00:00Z")
+            .unwrap()
+            .to_utc();
+        let line = scope(Some("m1".to_string()), async {
+            set_order("o1");
+            format_record(
+                &Record::builder()
+                    .args(format_args!("Order from 79146795555@c.us"))
+                    .level(Level::Info)
+                    .target("poller")
+                    .build(),
+                now,
+                true,
+                true,
+            )
+        })
+        .await;
+        let value: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["message"], "Order from ***5555@c.us");
+        assert_eq!(value["message_id"], "m1");
+        assert_eq!(value["order_id"], "o1");
+        assert_eq!(value["time"], "2026-10-19T10:00:00.000Z");
+
+        let line = format_record(
+            &Record::builder()
+                .args(format_args!("Start polling..."))
+                .level(Level::Info)
+                .target("poller")
+                .build(),
+            now,
+            false,
+            true,
+        );
+        assert_eq!(
+            line,
+            "2026-10-19T10:00:00.000Z INFO  poller > Start polling..."
+        );
+    }
+}
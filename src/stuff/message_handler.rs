@@ -1,153 +1,611 @@
 use crate::config::config;
-use crate::stuff::data_types::{Message, OrderState, ReceivedMessage};
+use crate::stuff::data_types::{
+    DeliveryStatus, Fit, InstanceState, Message, MessageStatus, Order, OrderEvent, OrderStage,
+    ReceivedImage, ReceivedMessage,
+};
 use crate::stuff::error::{Error, Result};
+use crate::stuff::health::channel_label;
+use crate::stuff::logging;
+use crate::stuff::metrics::metrics;
+use crate::stuff::multi::split_chat_id;
+use crate::stuff::outbox::DeliveryFailure;
+use crate::stuff::payment::{PaymentEvent, PaymentProvider, PaymentStatus};
 use crate::stuff::prompt::Prompt;
+use crate::stuff::production::Production;
+use crate::stuff::report::{DailyReport, SaleEvent, SalesLog, split_csv, to_csv};
 use crate::stuff::repository::Repository;
+use crate::stuff::schedule::Schedule;
+use crate::stuff::sink::OrderSink;
+use crate::stuff::worker_api::{FieldError, WorkerEvent, WorkerStatus};
 use crate::stuff::transport::Transport;
-use log::{error, info};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Как часто напоминать администратору об отказе Green API
+const INSTANCE_ALERT_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Пауза перед первым повтором передачи заказа, дальше она удваивается
+const SUBMIT_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_SUBMIT_RETRY_INTERVAL: Duration = Duration::from_secs(30 * 60);
+/// После стольких неудачных попыток заказ передается администратору
+const MAX_SUBMIT_ATTEMPTS: u32 = 10;
+/// Длина части CSV в чате, Telegram не принимает сообщения длиннее 4096 символов
+const REPORT_MESSAGE_LIMIT: usize = 4000;
 
 pub trait MessageHandler {
     async fn handle(&mut self, message: Message) -> Result<()>;
     async fn handle_awaits(&mut self) -> Result<()>;
+    async fn handle_payment(&mut self, event: PaymentEvent) -> Result<()>;
+    async fn handle_delivery_failure(&mut self, failure: DeliveryFailure) -> Result<()>;
+    async fn handle_worker_event(&mut self, event: WorkerEvent) -> Result<()>;
+}
+
+/// Пауза после `attempts` неудачных попыток: 1, 2, 4... минут, не больше 30
+fn retry_interval(attempts: u32) -> Duration {
+    SUBMIT_RETRY_INTERVAL
+        .saturating_mul(1 << attempts.saturating_sub(1).min(16))
+        .min(MAX_SUBMIT_RETRY_INTERVAL)
 }
 
-pub struct Handler<'a, R, T>
+/// Заказ, ожидающий повторной передачи в печать
+struct RetrySubmit {
+    order: Order,
+    /// Неудачных попыток
+    attempts: u32,
+    next_at: Instant,
+}
+
+pub struct Handler<'a, R, T, S, P>
 where
     R: Repository,
     T: Transport,
+    S: OrderSink,
+    P: PaymentProvider,
 {
     repository: R,
     transport: &'a T,
+    sink: S,
+    payments: Option<P>,
     prompt: Prompt,
+    schedule: Schedule,
+    production: Production,
+    /// Заказы, оформленные в нерабочее время и ожидающие открытия
+    held: Vec<Order>,
+    /// Заказы, не переданные из-за сбоя сервиса печати
+    retry: Vec<RetrySubmit>,
+    /// Чаты, которых нет или где бот заблокирован
+    unreachable: HashSet<String>,
+    /// Последнее оповещение о проблеме по имени канала
+    last_instance_alert: HashMap<String, Instant>,
+    /// Платежи заказов, уже переданных в работу: заказ удален, повтор уведомления не тревога
+    processed_payments: HashSet<String>,
+    /// Журнал заказов для отчетов, без него отчеты не ведутся
+    sales: Option<SalesLog>,
 }
 
-impl<'a, R, T> Handler<'a, R, T>
+impl<'a, R, T, S, P> Handler<'a, R, T, S, P>
 where
-    R: Repository + std::fmt::Debug,
+    R: Repository,
     T: Transport,
+    S: OrderSink,
+    P: PaymentProvider,
 {
-    pub fn new(repository: R, transport: &'a T) -> Self {
+    pub fn new(repository: R, transport: &'a T, sink: S, payments: Option<P>) -> Self {
         Self {
             repository,
             transport,
+            sink,
+            payments,
             prompt: Prompt::new(),
+            schedule: Schedule::new(),
+            production: Production::new(),
+            held: vec![],
+            retry: vec![],
+            unreachable: HashSet::new(),
+            last_instance_alert: HashMap::new(),
+            processed_payments: HashSet::new(),
+            sales: None,
+        }
+    }
+
+    pub fn with_sales_log(mut self, sales: SalesLog) -> Self {
+        self.sales = Some(sales);
+        self
+    }
+
+    fn record_sale(&self, event: SaleEvent, order: &Order) {
+        if let Some(sales) = &self.sales {
+            sales.append(event, order);
         }
     }
 
-    async fn handle_image_message(&mut self, message: ReceivedMessage) -> Result<()> {
+    async fn handle_image_message(&mut self, message: ReceivedImage) -> Result<()> {
         let order_option = self.repository.get_order(&message.chat_id);
-        if let Some(order) = order_option {
+        if let Some(order) = &order_option {
+            logging::set_order(&order.trace_id);
+        }
+        if let Some(order) = order_option.as_ref().filter(|o| o.payment().is_some()) {
+            // Состав заказа уже зафиксирован в ссылке на оплату
+            self.send_payment_pending(order.chat_id.clone()).await;
+        } else if let Some(order) = order_option {
             let mut updated = order.clone();
-            updated.add_image(message.message);
-            self.send_receive_file_confirmation(updated.get_chat_id(), updated.files_count())
+            updated.add_image(message.file);
+            self.send_receive_file_confirmation(updated.chat_id.clone(), updated.files_count())
                 .await;
+            info!("Image added, {} files", updated.files_count());
             self.repository.set_order(updated);
-            info!("Order updated in repo {:#?}", self.repository);
         } else {
-            let new_order = OrderState::from_img_msg(message);
-            self.send_receive_file_confirmation(new_order.get_chat_id(), new_order.files_count())
+            let new_order = self.with_default_product(Order::from_img_msg(message))?;
+            logging::set_order(&new_order.trace_id);
+            self.send_off_hours_notice(new_order.chat_id.clone()).await;
+            self.send_receive_file_confirmation(new_order.chat_id.clone(), new_order.files_count())
                 .await;
+            info!("Order created with image, stage {}", new_order.stage.name());
+            self.record_sale(SaleEvent::Started, &new_order);
             self.repository.set_order(new_order);
-            info!("Order created in repo {:#?}", self.repository);
         }
         Ok(())
     }
 
     async fn handle_text_message(&mut self, message: ReceivedMessage) -> Result<()> {
         let chat_id = message.chat_id.clone();
+        if chat_id == config().ADMIN_CHAT_ID && message.message.trim().to_lowercase() == "заказы"
+        {
+            self.send_orders_report(chat_id).await;
+            return Ok(());
+        }
+        if chat_id == config().ADMIN_CHAT_ID
+            && message.message.trim().to_lowercase().replace('ё', "е") == "отчет"
+        {
+            self.send_sales_report(chat_id).await;
+            return Ok(());
+        }
+        if message.message.to_lowercase().contains("помощь") {
+            self.send_help(chat_id).await;
+            return Ok(());
+        }
         let order_option = self.repository.get_order(&message.chat_id);
         if let Some(order) = order_option {
+            logging::set_order(&order.trace_id);
             // Клиент пожелал отменить заказ
             if message.message.to_lowercase().contains("отмен") {
                 self.repository.delete_order(&chat_id)?;
+                self.record_sale(SaleEvent::Canceled, &order);
                 self.send_cancel(chat_id).await;
                 return Ok(());
             }
 
-            match order {
-                OrderState::RaperRequested { .. } => {
-                    let res = self.try_set_paper(order, message);
+            // Клиент хочет вернуться на шаг назад или изменить выбранный вариант
+            let text = message.message.trim().to_lowercase();
+            if text.starts_with("назад") || text.starts_with("изменить") {
+                self.change_order(order, &text).await?;
+                return Ok(());
+            }
+
+            let stage = order.stage.name();
+            match order.stage {
+                OrderStage::ProductRequested | OrderStage::OptionsRequested { .. } => {
+                    let res = self.try_set_option(order.clone(), message);
                     match res {
-                        Ok(paper) => {
-                            self.send_size_request(chat_id.clone(), &paper).await;
+                        Ok(updated) => {
+                            self.send_order_request(&updated).await;
                         }
-                        Err(_) => {
-                            self.send_paper_request(chat_id).await;
+                        Err(e) => {
+                            error!("Option invalid: {:?}", e);
+                            self.send_order_request(&order).await;
                         }
                     }
                 }
 
-                OrderState::SizeRequested { .. } => {
-                    let res = self.try_set_size(order, message);
+                OrderStage::FitRequested { .. } => {
+                    let res = self.try_set_fit(order.clone(), message);
                     match res {
-                        Ok(_) => {
-                            self.send_ready_request(chat_id.clone()).await;
+                        Ok(updated) => {
+                            self.send_order_request(&updated).await;
                         }
-                        Err(Error::SizeInvalid(paper)) => {
-                            error!("Paper size invalid: {:?}", paper);
-                            self.send_size_request(chat_id.clone(), &paper).await;
+                        Err(e) => {
+                            error!("Fit invalid: {:?}", e);
+                            self.send_order_request(&order).await;
                         }
-                        _ => {}
                     }
                 }
 
-                OrderState::SizeSelected { .. } => {
+                OrderStage::Ready { .. } => {
                     if message.message.to_lowercase().contains("готов") && order.have_files() {
-                        self.send_wait_request(chat_id.clone()).await;
-                        let res = self.transport.send_order(order).await;
-                        self.repository.delete_order(&chat_id)?;
-                        match res {
-                            Ok(order_id) => {
-                                info!("Order from {} DONE with id {}", chat_id, order_id);
-                                self.send_final_request(chat_id, order_id).await;
-                            }
-                            Err(_) => {
-                                self.send_error_request(chat_id).await;
-                            }
+                        if self.payments.is_some() {
+                            self.request_payment(order).await?;
+                        } else {
+                            self.submit_order(order).await?;
                         }
                     } else {
                         self.send_ready_request(chat_id).await;
                     }
                 }
+
+                OrderStage::PaymentRequested { .. } => {
+                    self.send_payment_request(&order).await;
+                }
             }
-            info!("Order updated {:#?}", self.repository);
+            info!("Order updated from stage {}", stage);
+        } else if let Some(answer) = self.prompt.faq_answer(&message.message, &self.schedule) {
+            // На вопрос отвечаем без создания заказа
+            self.send_faq_answer(chat_id, answer).await;
         } else {
-            self.repository.set_order(OrderState::from_txt_msg(message));
-            info!("Order created {:#?}", self.repository);
-            self.send_paper_request(chat_id).await;
+            let new_order = self.with_default_product(Order::from_txt_msg(message))?;
+            logging::set_order(&new_order.trace_id);
+            self.send_off_hours_notice(chat_id).await;
+            self.send_order_request(&new_order).await;
+            info!("Order created, stage {}", new_order.stage.name());
+            self.record_sale(SaleEvent::Started, &new_order);
+            self.repository.set_order(new_order);
+        }
+        Ok(())
+    }
+
+    fn with_default_product(&self, order: Order) -> Result<Order> {
+        match self.prompt.default_product() {
+            Some(product) => Ok(order.apply(OrderEvent::ProductChosen(product))?),
+            None => Ok(order),
+        }
+    }
+
+    async fn change_order(&mut self, order: Order, text: &str) -> Result<()> {
+        let target: Vec<&str> = text.split_whitespace().skip(1).collect();
+        let event = if text.starts_with("назад") {
+            Some(OrderEvent::Back)
+        } else if target.is_empty() {
+            None
+        } else {
+            self.change_event(&order, &target)
+        };
+        let Some(event) = event else {
+            self.send_change_request(&order).await;
+            return Ok(());
+        };
+        match order.clone().apply(event) {
+            Ok(mut updated) => {
+                if updated.stage == OrderStage::ProductRequested {
+                    updated = self.with_default_product(updated)?;
+                }
+                self.send_order_request(&updated).await;
+                self.repository.set_order(updated);
+            }
+            Err(e) => {
+                error!("Order change rejected: {}", e);
+                self.send_order_request(&order).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Что клиент хочет изменить: "изменить размер", "изменить тип бумаги"
+    fn change_event(&self, order: &Order, target: &[&str]) -> Option<OrderEvent> {
+        let mentioned = |name: &str| {
+            name.to_lowercase()
+                .split_whitespace()
+                .any(|n| target.iter().any(|t| same_word(t, n)))
+        };
+        if mentioned("товар") {
+            return Some(OrderEvent::ChangeProduct);
+        }
+        if mentioned("кадрирование обрезка") {
+            return Some(OrderEvent::ChangeFit);
+        }
+        order
+            .selections
+            .iter()
+            .position(|s| mentioned(&s.group))
+            .map(OrderEvent::ChangeOption)
+    }
+
+    async fn request_payment(&mut self, order: Order) -> Result<()> {
+        let Some(payments) = &self.payments else {
+            return Ok(());
+        };
+        let chat_id = order.chat_id.clone();
+        match payments.create_payment(&order).await {
+            Ok(payment) => {
+                info!("Payment {} created for {}", payment.id, chat_id);
+                let updated = order.apply(OrderEvent::PaymentCreated(payment))?;
+                self.send_payment_request(&updated).await;
+                self.repository.set_order(updated);
+            }
+            Err(e) => {
+                error!("Failed to create payment for {}: {}", chat_id, e);
+                self.send_error_request(chat_id).await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn submit_order(&mut self, order: Order) -> Result<()> {
+        let chat_id = order.chat_id.clone();
+        self.repository.delete_order(&chat_id)?;
+        if order.is_paid()
+            && let Some(payment_id) = order.payment_id()
+        {
+            self.processed_payments.insert(payment_id.to_string());
+        }
+        if config().HOLD_OFF_HOURS && !self.schedule.is_open(Utc::now()) {
+            info!("Order from {} held until opening", chat_id);
+            self.held.push(order);
+            self.send_hold_request(chat_id).await;
+            return Ok(());
+        }
+        self.send_to_worker(order).await;
+        Ok(())
+    }
+
+    async fn send_to_worker(&mut self, order: Order) {
+        logging::set_order(&order.trace_id);
+        self.send_wait_request(order.chat_id.clone()).await;
+        self.submit_to_sink(order, 0).await;
+    }
+
+    /// `attempts` - число прошлых неудачных попыток, после первой клиент и администратор
+    /// уже знают о сбое
+    async fn submit_to_sink(&mut self, mut order: Order, attempts: u32) {
+        let chat_id = order.chat_id.clone();
+        order.ready_at = self
+            .production
+            .estimate(&order, &self.schedule, Utc::now());
+        let res = self.sink.submit(order.clone()).await;
+        match res {
+            Ok(submitted) => {
+                info!("Order from {} DONE with id {}", chat_id, submitted.order_id);
+                metrics().order_submitted("ok");
+                self.record_sale(SaleEvent::Submitted, &order);
+                let ready_at = submitted.ready_at.or(order.ready_at);
+                if let Some(ready) = ready_at {
+                    self.production.enqueue(ready);
+                }
+                self.send_final_request(chat_id, submitted.order_id, ready_at)
+                    .await;
+            }
+            Err(Error::OrderRejected(errors)) if order.is_paid() => {
+                warn!("Paid order from {} rejected: {:?}", chat_id, errors);
+                metrics().order_submitted("rejected");
+                // Оплаченный заказ клиент изменить не может, его разбирает администратор
+                self.alert_admin(format!(
+                    "Оплаченный заказ из чата {} не принят: {:?}. Платеж {}, свяжитесь с клиентом\n{}",
+                    chat_id,
+                    errors,
+                    order.payment_id().unwrap_or_default(),
+                    order
+                ))
+                .await;
+                self.send_paid_rejected_request(chat_id, &errors).await;
+            }
+            Err(Error::OrderRejected(errors)) => {
+                warn!("Order from {} rejected: {:?}", chat_id, errors);
+                metrics().order_submitted("rejected");
+                // Клиент исправляет параметры и отправляет заказ повторно
+                order.ready_at = None;
+                self.repository.set_order(order);
+                self.send_rejected_request(chat_id, &errors).await;
+            }
+            Err(e) if e.is_refusal() => {
+                error!("Order from {} refused: {}", chat_id, e);
+                metrics().order_submitted("failed");
+                self.alert_admin(format!(
+                    "Заказ из чата {} не принят в печать: {}. Свяжитесь с клиентом\n{}",
+                    chat_id, e, order
+                ))
+                .await;
+            }
+            Err(e) => {
+                error!("Order from {} not submitted: {}", chat_id, e);
+                metrics().order_submitted("failed");
+                let attempts = attempts + 1;
+                if attempts >= MAX_SUBMIT_ATTEMPTS {
+                    self.alert_admin(format!(
+                        "Заказ из чата {} не передан в печать за {} попыток: {}. Свяжитесь с клиентом\n{}",
+                        chat_id, attempts, e, order
+                    ))
+                    .await;
+                    return;
+                }
+                if attempts == 1 {
+                    self.alert_admin(format!(
+                        "Заказ из чата {} не передан в печать, будет повтор: {}",
+                        chat_id, e
+                    ))
+                    .await;
+                    self.send_retry_request(chat_id).await;
+                }
+                // Заказ уже удален из репозитория, поэтому сохраняется для повтора
+                order.ready_at = None;
+                self.retry.push(RetrySubmit {
+                    order,
+                    attempts,
+                    next_at: Instant::now() + retry_interval(attempts),
+                });
+            }
+        }
+    }
+
+    /// Повтор передачи заказов, у которых истекла пауза
+    async fn retry_submissions(&mut self) {
+        let now = Instant::now();
+        let (due, waiting) = std::mem::take(&mut self.retry)
+            .into_iter()
+            .partition(|r| r.next_at <= now);
+        self.retry = waiting;
+        for r in due {
+            logging::set_order(&r.order.trace_id);
+            info!(
+                "Retrying order from {}, attempt {}",
+                r.order.chat_id,
+                r.attempts + 1
+            );
+            self.submit_to_sink(r.order, r.attempts).await;
+        }
+    }
+
+    /// Неоплаченная ссылка отменяется у провайдера до удаления заказа
+    async fn expire_payment(&mut self, order: Order) -> Result<()> {
+        let (Some(payments), Some(payment_id)) = (&self.payments, order.payment_id()) else {
+            return Ok(());
+        };
+        let payment_id = payment_id.to_string();
+        // Уведомление об оплате могло не дойти
+        match payments.payment_status(&payment_id).await {
+            Ok(PaymentStatus::Succeeded) => {
+                return self.handle_payment(PaymentEvent { payment_id }).await;
+            }
+            Ok(PaymentStatus::Pending) => {
+                if let Err(e) = payments.cancel_payment(&payment_id).await {
+                    // Заказ остается до следующего цикла
+                    error!("Payment {} not canceled: {}", payment_id, e);
+                    return Ok(());
+                }
+            }
+            Ok(PaymentStatus::Canceled) => {}
+            Err(e) => {
+                error!("Payment {} status unknown: {}", payment_id, e);
+                return Ok(());
+            }
+        }
+        info!("Payment {} expired", payment_id);
+        self.repository.delete_order(&order.chat_id)?;
+        metrics().await_action("timeout");
+        self.record_sale(SaleEvent::Timeout, &order);
+        self.transport
+            .send_message(order.chat_id, self.prompt.payment_expired_prompt())
+            .await
+    }
+
+    fn try_set_option(&mut self, o: Order, message: ReceivedMessage) -> Result<Order> {
+        let idx = message
+            .message
+            .trim()
+            .parse::<usize>()?
+            .checked_sub(1)
+            .ok_or(Error::OptionInvalid)?;
+        let event = match o.product() {
+            None => {
+                let product = self.prompt.try_get_product(idx);
+                info!("product {:?}", product);
+                OrderEvent::ProductChosen(product.ok_or(Error::OptionInvalid)?)
+            }
+            Some(product) => {
+                let selection = self.prompt.try_get_option(product, &o.selections, idx);
+                info!("selection {:?}", selection);
+                OrderEvent::OptionChosen(selection.ok_or(Error::OptionInvalid)?)
+            }
+        };
+        let mut new_state = o.apply(event)?;
+        if let Some(product) = new_state.product()
+            && let Some(price) = self.prompt.options_price(product, &new_state.selections)
+        {
+            let fit = new_state.preset_fit();
+            let fit_required =
+                fit.is_none() && self.prompt.print_ratio(&new_state.selections).is_some();
+            new_state = new_state.apply(OrderEvent::OptionsCompleted {
+                price,
+                fit_required,
+                fit,
+            })?;
         }
+        self.repository.set_order(new_state.clone());
+        Ok(new_state)
+    }
+
+    fn try_set_fit(&mut self, o: Order, message: ReceivedMessage) -> Result<Order> {
+        let fit = match message.message.trim() {
+            "1" => Fit::Crop,
+            "2" => Fit::Fit,
+            _ => return Err(Error::OptionInvalid),
+        };
+        let new_state = o.apply(OrderEvent::FitChosen(fit))?;
+        self.repository.set_order(new_state.clone());
+        Ok(new_state)
+    }
+
+    /// Клиенту невозможно доставить сообщения: заказ отменяется без уведомления
+    async fn mark_unreachable(&mut self, chat_id: String, reason: String) -> Result<()> {
+        if !self.unreachable.insert(chat_id.clone()) {
+            return Ok(());
+        }
+        warn!("Chat {} is unreachable: {}", chat_id, reason);
+        if let Some(order) = self.repository.get_order(&chat_id) {
+            self.repository.delete_order(&chat_id)?;
+            self.record_sale(SaleEvent::Canceled, &order);
+        }
+        self.alert_admin(format!(
+            "Чат {} недоступен, заказ отменен: {}",
+            chat_id, reason
+        ))
+        .await;
         Ok(())
     }
 
-    fn try_set_paper(&mut self, o: OrderState, message: ReceivedMessage) -> Result<String> {
-        let paper_type: usize = message.message.parse()?;
-        let paper_opt = self.prompt.try_get_paper(paper_type - 1);
-        info!("paper_opt {:?}", paper_opt);
-        match paper_opt {
-            None => Err(Error::PaperInvalid),
-            Some(paper) => {
-                let new_state = o.into_order_with_paper(paper.clone())?;
-                self.repository.set_order(new_state);
-                Ok(paper)
+    /// Сообщение о проблеме с каналом не чаще INSTANCE_ALERT_INTERVAL для каждого канала
+    async fn alert_instance(&mut self, channel: &str, text: String) {
+        let alerted = self
+            .last_instance_alert
+            .get(channel)
+            .is_some_and(|t| t.elapsed() < INSTANCE_ALERT_INTERVAL);
+        if !alerted {
+            self.last_instance_alert
+                .insert(channel.to_string(), Instant::now());
+            self.alert_admin(text).await;
+        }
+    }
+
+    async fn handle_status(&mut self, status: MessageStatus) -> Result<()> {
+        match status.status {
+            DeliveryStatus::NoAccount => {
+                self.mark_unreachable(status.chat_id, "нет аккаунта WhatsApp".to_string())
+                    .await?;
             }
+            DeliveryStatus::Failed => {
+                warn!(
+                    "Message {} to {} failed",
+                    status.message_id, status.chat_id
+                );
+            }
+            _ => {}
         }
+        Ok(())
     }
 
-    fn try_set_size(&mut self, o: OrderState, message: ReceivedMessage) -> Result<()> {
-        let size_type: usize = message.message.parse()?;
-        let paper = o.get_paper().to_string();
-        let size_opt = self.prompt.try_get_size(o.get_paper(), size_type - 1);
-        info!("size_opt {:?}", size_opt);
-        match size_opt {
-            None => Err(Error::SizeInvalid(paper)),
-            Some((size, price)) => {
-                let new_state = o.into_order_with_size(size, price)?;
-                self.repository.set_order(new_state);
-                Ok(())
+    async fn handle_instance_state(&mut self, channel: String, state: InstanceState) {
+        let label = channel_label(&channel);
+        match state {
+            InstanceState::Authorized => info!("Channel {} authorized", label),
+            InstanceState::Starting | InstanceState::SleepMode | InstanceState::Other => {
+                warn!("Channel {} state {:?}", label, state);
+            }
+            InstanceState::NotAuthorized | InstanceState::Blocked | InstanceState::YellowCard => {
+                error!("Channel {} state {:?}", label, state);
+                self.alert_instance(&channel, format!("Канал {} в состоянии {:?}", label, state))
+                    .await;
             }
         }
     }
 
+    async fn send_call_reply(&self, chat_id: String) {
+        let res = self
+            .transport
+            .send_message(chat_id, self.prompt.call_prompt())
+            .await;
+        if let Err(e) = res {
+            error!("Error sending call reply: {}", e);
+        };
+    }
+
+    async fn alert_admin(&self, text: String) {
+        let res = self
+            .transport
+            .send_message(config().ADMIN_CHAT_ID.clone(), text)
+            .await;
+        if let Err(e) = res {
+            error!("Error sending admin alert: {}", e);
+        };
+    }
+
     async fn send_receive_file_confirmation(&self, chat_id: String, count: usize) {
         let res = self
             .transport
@@ -158,23 +616,128 @@ where
         };
     }
 
-    async fn send_paper_request(&self, chat_id: String) {
+    /// Повторяет клиенту вопрос текущего этапа заказа
+    async fn send_order_request(&self, order: &Order) {
+        let chat_id = order.chat_id.clone();
+        let text = match &order.stage {
+            OrderStage::ProductRequested => self.prompt.product_prompt(),
+            OrderStage::OptionsRequested { product } => {
+                self.prompt.option_prompt(product, &order.selections)
+            }
+            OrderStage::FitRequested { .. } => {
+                self.prompt.fit_prompt(&order.selections, &order.files)
+            }
+            OrderStage::Ready { .. } => self.prompt.ready_prompt(),
+            OrderStage::PaymentRequested { .. } => {
+                self.send_payment_request(order).await;
+                return;
+            }
+        };
+        let res = self.transport.send_message(chat_id, text).await;
+        if let Err(e) = res {
+            error!("Error sending order request: {}", e);
+        };
+    }
+
+    async fn send_off_hours_notice(&self, chat_id: String) {
+        let now = Utc::now();
+        if !config().OFF_HOURS_NOTICE || self.schedule.is_open(now) {
+            return;
+        }
         let res = self
             .transport
-            .send_message(chat_id, self.prompt.paper_prompt())
+            .send_message(
+                chat_id,
+                self.prompt.off_hours_prompt(self.schedule.next_opening(now)),
+            )
             .await;
         if let Err(e) = res {
-            error!("Error sending paper request: {}", e);
+            error!("Error sending off hours notice: {}", e);
+        };
+    }
+
+    async fn send_hold_request(&self, chat_id: String) {
+        let opening = self.schedule.next_opening(Utc::now());
+        let res = self
+            .transport
+            .send_message(chat_id, self.prompt.hold_prompt(opening))
+            .await;
+        if let Err(e) = res {
+            error!("Error sending hold request: {}", e);
         };
     }
 
-    async fn send_size_request(&self, chat_id: String, paper: &str) {
+    async fn send_help(&self, chat_id: String) {
         let res = self
             .transport
-            .send_message(chat_id, self.prompt.size_prompt(paper))
+            .send_message(chat_id, self.prompt.help_prompt())
             .await;
         if let Err(e) = res {
-            error!("Error sending size request: {}", e);
+            error!("Error sending help: {}", e);
+        };
+    }
+
+    async fn send_orders_report(&self, chat_id: String) {
+        let mut orders: Vec<Order> = self.repository.get_orders().into_values().collect();
+        orders.sort_by(|a, b| a.chat_id.cmp(&b.chat_id));
+        let report = self.prompt.orders_report(&orders, &self.held);
+        let res = self.transport.send_message(chat_id, report).await;
+        if let Err(e) = res {
+            error!("Error sending orders report: {}", e);
+        };
+    }
+
+    /// Итоги текущего дня по журналу продаж и его события в CSV
+    async fn send_sales_report(&self, chat_id: String) {
+        let Some(sales) = &self.sales else {
+            self.send_report_text(chat_id, "Журнал продаж не ведется".to_string())
+                .await;
+            return;
+        };
+        let tz = self.schedule.timezone();
+        let today = self.schedule.local(Utc::now()).date_naive();
+        match sales.read(today, today, tz) {
+            Ok(records) => {
+                let summary = DailyReport::new(today, &records).summary();
+                self.send_report_text(chat_id.clone(), summary).await;
+                if !records.is_empty() {
+                    for part in split_csv(&to_csv(&records, tz), REPORT_MESSAGE_LIMIT) {
+                        self.send_report_text(chat_id.clone(), part).await;
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Error reading sales log: {}", e);
+                self.send_report_text(chat_id, format!("Отчет недоступен: {}", e))
+                    .await;
+            }
+        }
+    }
+
+    async fn send_report_text(&self, chat_id: String, text: String) {
+        let res = self.transport.send_message(chat_id, text).await;
+        if let Err(e) = res {
+            error!("Error sending sales report: {}", e);
+        };
+    }
+
+    async fn send_faq_answer(&self, chat_id: String, answer: String) {
+        let res = self.transport.send_message(chat_id, answer).await;
+        if let Err(e) = res {
+            error!("Error sending FAQ answer: {}", e);
+        };
+    }
+
+    async fn send_change_request(&self, order: &Order) {
+        let res = self
+            .transport
+            .send_message(
+                order.chat_id.clone(),
+                self.prompt.change_prompt(&order.selections, order.fit()),
+            )
+            .await;
+        if let Err(e) = res {
+            error!("Error sending change request: {}", e);
         };
     }
 
@@ -188,6 +751,45 @@ where
         };
     }
 
+    async fn send_payment_request(&self, order: &Order) {
+        let (Some(payment), Some(total)) = (order.payment(), order.total()) else {
+            return;
+        };
+        let res = self
+            .transport
+            .send_message(
+                order.chat_id.clone(),
+                self.prompt.payment_prompt(&payment.url, total),
+            )
+            .await;
+        if let Err(e) = res {
+            error!("Error sending payment request: {}", e);
+        };
+    }
+
+    async fn send_payment_pending(&self, chat_id: String) {
+        let res = self
+            .transport
+            .send_message(
+                chat_id,
+                "Заказ ожидает оплаты, новые файлы не добавлены".to_string(),
+            )
+            .await;
+        if let Err(e) = res {
+            error!("Error sending payment pending: {}", e);
+        };
+    }
+
+    async fn send_payment_failed(&self, chat_id: String) {
+        let res = self
+            .transport
+            .send_message(chat_id, self.prompt.payment_failed_prompt())
+            .await;
+        if let Err(e) = res {
+            error!("Error sending payment failed: {}", e);
+        };
+    }
+
     async fn send_wait_request(&self, chat_id: String) {
         let res = self
             .transport
@@ -201,16 +803,52 @@ where
         };
     }
 
-    async fn send_final_request(&self, chat_id: String, order_id: String) {
+    async fn send_final_request(
+        &self,
+        chat_id: String,
+        order_id: String,
+        ready_at: Option<DateTime<Utc>>,
+    ) {
+        let ready = ready_at.map(|t| self.schedule.local(t));
         let res = self
             .transport
-            .send_message(chat_id, self.prompt.final_prompt(order_id))
+            .send_message(chat_id, self.prompt.final_prompt(order_id, ready))
             .await;
         if let Err(e) = res {
             error!("Error sending final request: {}", e);
         };
     }
 
+    async fn send_rejected_request(&self, chat_id: String, errors: &[FieldError]) {
+        let res = self
+            .transport
+            .send_message(chat_id, self.prompt.rejected_prompt(errors))
+            .await;
+        if let Err(e) = res {
+            error!("Error sending rejected request: {}", e);
+        };
+    }
+
+    async fn send_paid_rejected_request(&self, chat_id: String, errors: &[FieldError]) {
+        let res = self
+            .transport
+            .send_message(chat_id, self.prompt.paid_rejected_prompt(errors))
+            .await;
+        if let Err(e) = res {
+            error!("Error sending paid rejected request: {}", e);
+        };
+    }
+
+    async fn send_retry_request(&self, chat_id: String) {
+        let res = self
+            .transport
+            .send_message(chat_id, self.prompt.retry_prompt())
+            .await;
+        if let Err(e) = res {
+            error!("Error sending retry request: {}", e);
+        };
+    }
+
     async fn send_error_request(&self, chat_id: String) {
         let res = self
             .transport
@@ -235,12 +873,35 @@ where
     }
 }
 
-impl<R, T> MessageHandler for Handler<'_, R, T>
+/// Сравнение слов без учета окончаний: "бумагу" и "бумаги"
+fn same_word(a: &str, b: &str) -> bool {
+    let stem = |w: &str| w.chars().take(4).collect::<String>();
+    if a.chars().count() >= 4 && b.chars().count() >= 4 {
+        stem(a) == stem(b)
+    } else {
+        a == b
+    }
+}
+
+impl<R, T, S, P> MessageHandler for Handler<'_, R, T, S, P>
 where
-    R: Repository + std::fmt::Debug,
+    R: Repository,
     T: Transport,
+    S: OrderSink,
+    P: PaymentProvider,
 {
     async fn handle(&mut self, message: Message) -> Result<()> {
+        let chat_id = match &message {
+            Message::Text(msg) => Some(&msg.chat_id),
+            Message::Image(msg) => Some(&msg.chat_id),
+            Message::Call(chat_id) => Some(chat_id),
+            _ => None,
+        };
+        if let Some(chat_id) = chat_id
+            && self.unreachable.remove(chat_id)
+        {
+            info!("Chat {} is reachable again", chat_id);
+        }
         match message {
             Message::Text(msg) => {
                 self.handle_text_message(msg).await?;
@@ -248,57 +909,174 @@ where
             Message::Image(msg) => {
                 self.handle_image_message(msg).await?;
             }
+            Message::Status(status) => {
+                self.handle_status(status).await?;
+            }
+            Message::InstanceState(channel, state) => {
+                self.handle_instance_state(channel, state).await;
+            }
+            Message::Call(chat_id) => {
+                self.send_call_reply(chat_id).await;
+            }
             Message::Empty => {}
         }
         Ok(())
     }
 
     async fn handle_awaits(&mut self) -> Result<()> {
+        if !self.held.is_empty() && self.schedule.is_open(Utc::now()) {
+            for order in std::mem::take(&mut self.held) {
+                info!("Submitting held order from {}", order.chat_id);
+                self.send_to_worker(order).await;
+            }
+        }
+        self.retry_submissions().await;
         let orders = self.repository.get_orders();
         let mut orders_to_remove = vec![];
         for (_, o) in orders {
+            logging::set_order(&o.trace_id);
+            if o.payment().is_some() {
+                // Ссылка на оплату живет дольше ожидания ответа, оплаченный заказ не удаляется
+                if !o.is_paid() && o.last_time_sec() > config().PAYMENT_TIMEOUT {
+                    self.expire_payment(o).await?;
+                }
+                continue;
+            }
             match o.have_files() {
                 true => {
-                    if o.repeats() < config().REPEAT_COUNT
+                    if o.repeats < config().REPEAT_COUNT
                         && o.last_time_sec() > config().REPEAT_TIMEOUT
                     {
                         let mut clonned = o.clone();
                         clonned.requested();
                         self.repository.set_order(clonned);
-                        match o {
-                            OrderState::RaperRequested { .. } => {
-                                self.send_paper_request(o.get_chat_id()).await;
-                            }
-                            OrderState::SizeRequested { .. } => {
-                                self.send_size_request(o.get_chat_id(), o.get_paper()).await;
-                            }
-                            OrderState::SizeSelected { .. } => {
-                                self.send_ready_request(o.get_chat_id()).await;
-                            }
-                        }
-                    } else if o.repeats() < config().REPEAT_COUNT
+                        metrics().await_action("reminder");
+                        self.send_order_request(&o).await;
+                    } else if o.repeats < config().REPEAT_COUNT
                         && o.last_time_sec() < config().REPEAT_TIMEOUT
                     {
                     } else {
-                        orders_to_remove.push(o.get_chat_id());
+                        orders_to_remove.push(o.clone());
                     }
                 }
                 false => {
                     if o.last_time_sec() > config().NO_FILES_TIMEOUT {
-                        orders_to_remove.push(o.get_chat_id());
+                        orders_to_remove.push(o.clone());
                     }
                 }
             }
         }
-        for chat_id in orders_to_remove {
-            self.repository.delete_order(&chat_id)?;
+        for order in orders_to_remove {
+            logging::set_order(&order.trace_id);
+            info!("Order canceled by timeout at stage {}", order.stage.name());
+            self.repository.delete_order(&order.chat_id)?;
+            metrics().await_action("timeout");
+            self.record_sale(SaleEvent::Timeout, &order);
             self.transport
                 .send_message(
-                    chat_id.clone(),
+                    order.chat_id,
                     "Заказ отменен, из-за длительного ожидания".to_string(),
                 )
                 .await?;
         }
+        metrics().set_orders(self.repository.get_orders().values());
+        Ok(())
+    }
+
+    async fn handle_payment(&mut self, event: PaymentEvent) -> Result<()> {
+        let Some(payments) = &self.payments else {
+            return Ok(());
+        };
+        let order = self
+            .repository
+            .get_orders()
+            .into_values()
+            .find(|o| o.payment_id() == Some(event.payment_id.as_str()));
+        let Some(order) = order else {
+            if self.processed_payments.contains(&event.payment_id) {
+                info!("Payment {} already processed", event.payment_id);
+                return Ok(());
+            }
+            // Заказ мог быть удален до оплаты, деньги клиента не должны потеряться
+            match payments.payment_status(&event.payment_id).await {
+                Ok(PaymentStatus::Succeeded) => {
+                    error!("Order for paid payment {} not found", event.payment_id);
+                    self.alert_admin(format!(
+                        "Получена оплата {} без заказа, свяжитесь с клиентом",
+                        event.payment_id
+                    ))
+                    .await;
+                }
+                _ => warn!("Order for payment {} not found", event.payment_id),
+            }
+            return Ok(());
+        };
+        logging::set_order(&order.trace_id);
+
+        match payments.payment_status(&event.payment_id).await? {
+            PaymentStatus::Pending => {}
+            PaymentStatus::Succeeded => {
+                info!("Payment {} succeeded", event.payment_id);
+                self.submit_order(order.apply(OrderEvent::PaymentSucceeded)?)
+                    .await?;
+            }
+            PaymentStatus::Canceled => {
+                info!("Payment {} canceled", event.payment_id);
+                let chat_id = order.chat_id.clone();
+                self.repository.set_order(order.apply(OrderEvent::PaymentFailed)?);
+                self.send_payment_failed(chat_id).await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_delivery_failure(&mut self, failure: DeliveryFailure) -> Result<()> {
+        let DeliveryFailure { chat_id, error } = failure;
+        if chat_id == config().ADMIN_CHAT_ID {
+            // Сообщить администратору некуда, остается только лог
+            error!("Admin alert not delivered: {}", error);
+            return Ok(());
+        }
+        if error.is_instance_failure() {
+            let channel = split_chat_id(&chat_id).map_or("", |(channel, _)| channel);
+            let label = channel_label(channel);
+            error!("Channel {} failure: {}", label, error);
+            self.alert_instance(
+                channel,
+                format!("Канал {} отклоняет сообщения: {}", label, error),
+            )
+            .await;
+            return Ok(());
+        }
+        match error {
+            Error::ChatUnreachable(_) => {
+                self.mark_unreachable(chat_id, error.to_string()).await?;
+            }
+            Error::ApiBadRequest(_) => {
+                // Отклонено само сообщение, например из-за длины, клиент по-прежнему доступен
+                error!("Message to {} rejected: {}", chat_id, error);
+            }
+            error => {
+                self.alert_admin(format!(
+                    "Не удалось отправить сообщение в чат {}: {}",
+                    chat_id, error
+                ))
+                .await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_worker_event(&mut self, event: WorkerEvent) -> Result<()> {
+        match event.status {
+            WorkerStatus::Printing => info!("Order {} is printing", event.order_id),
+            WorkerStatus::Ready => {
+                info!("Order {} is ready", event.order_id);
+                self.transport
+                    .send_message(event.chat_id, self.prompt.pickup_prompt(&event.order_id))
+                    .await?;
+            }
+        }
         Ok(())
     }
 }
@@ -306,15 +1084,70 @@ where
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::stuff::audit::AuditLog;
+    use crate::stuff::data_types::OrderFile;
+    use crate::stuff::money::{Currency, Money};
+    use crate::stuff::payment::FakePaymentProvider;
     use crate::stuff::repository::OrderRepository;
+    use crate::stuff::sink::FakeSink;
     use crate::stuff::transport::MockTransport;
+    use std::sync::atomic::Ordering;
+
+    type TestHandler =
+        Handler<'static, OrderRepository, MockTransport, FakeSink, FakePaymentProvider>;
+
+    fn handler(payments: Option<FakePaymentProvider>) -> TestHandler {
+        Handler::new(
+            OrderRepository::new(),
+            &MockTransport,
+            FakeSink::default(),
+            payments,
+        )
+    }
+
+    fn text(message: &str) -> ReceivedMessage {
+        ReceivedMessage {
+            chat_id: "79146795555@c.us".to_string(),
+            customer_name: "Andrey".to_string(),
+            message_id: None,
+            message: message.to_string(),
+        }
+    }
+
+    fn image() -> ReceivedImage {
+        ReceivedImage {
+            chat_id: "79146795555@c.us".to_string(),
+            customer_name: "Andrey".to_string(),
+            message_id: None,
+            file: OrderFile {
+                url: "http://localhost/1.jpg".to_string(),
+                dimensions: None,
+            },
+        }
+    }
+
+    /// Заказ с одним фото доходит до подтверждения, этап проверяется после каждого ответа
+    async fn order_to_ready(handler: &mut TestHandler) {
+        handler.handle(Message::Image(image())).await.unwrap();
+        let steps = [
+            ("1", "OptionsRequested"),
+            ("1", "OptionsRequested"),
+            ("1", "OptionsRequested"),
+            ("2", "FitRequested"),
+            ("2", "Ready"),
+        ];
+        for (answer, stage) in steps {
+            handler.handle_text_message(text(answer)).await.unwrap();
+            let order = handler.repository.get_order("79146795555@c.us").unwrap();
+            assert_eq!(order.stage.name(), stage, "answer {}", answer);
+        }
+    }
+
     #[tokio::test]
     async fn test_handle_text() {
-        let repo = OrderRepository::new();
-        let transport = MockTransport;
-        let mut handler = Handler::new(repo, &transport);
+        let mut handler = handler(None);
 
-        let msg = transport.receive_message().await.unwrap();
+        let msg = MockTransport.receive_message().await.unwrap();
         let res = handler.handle(msg).await;
         assert!(res.is_ok());
         println!("{:#?}", handler.repository);
@@ -322,6 +1155,7 @@ mod test {
         let paper_answer = ReceivedMessage {
             chat_id: "79146795555@c.us".to_string(),
             customer_name: "Andrey".to_string(),
+            message_id: None,
             message: "1".to_string(),
         };
         let res = handler.handle_text_message(paper_answer).await;
@@ -331,6 +1165,7 @@ mod test {
         let size_answer = ReceivedMessage {
             chat_id: "79146795555@c.us".to_string(),
             customer_name: "Andrey".to_string(),
+            message_id: None,
             message: "1".to_string(),
         };
         let res = handler.handle_text_message(size_answer).await;
@@ -340,10 +1175,291 @@ mod test {
         let size_answer = ReceivedMessage {
             chat_id: "79146795555@c.us".to_string(),
             customer_name: "Andrey".to_string(),
+            message_id: None,
             message: "Отмените".to_string(),
         };
         let res = handler.handle_text_message(size_answer).await;
         assert!(res.is_ok());
         println!("{:#?}", handler.repository);
     }
+
+    #[tokio::test]
+    async fn test_payment_flow() {
+        let mut handler = handler(Some(FakePaymentProvider::default()));
+        order_to_ready(&mut handler).await;
+        handler.handle_text_message(text("Готово")).await.unwrap();
+
+        let order = handler.repository.get_order("79146795555@c.us").unwrap();
+        let payment_id = order.payment_id().unwrap().to_string();
+        assert_eq!(order.total(), Some(Money::new(2200, Currency::Rub)));
+
+        let payments = handler.payments.as_ref().unwrap();
+        payments.set_status(&payment_id, PaymentStatus::Canceled);
+        let event = PaymentEvent {
+            payment_id: payment_id.clone(),
+        };
+        handler.handle_payment(event.clone()).await.unwrap();
+        let order = handler.repository.get_order("79146795555@c.us").unwrap();
+        assert!(matches!(order.stage, OrderStage::Ready { .. }));
+
+        handler.handle_text_message(text("Готово")).await.unwrap();
+        let payments = handler.payments.as_ref().unwrap();
+        payments.set_status(&payment_id, PaymentStatus::Succeeded);
+        handler.handle_payment(event.clone()).await.unwrap();
+        assert!(handler.repository.get_order("79146795555@c.us").is_none());
+        assert_eq!(handler.sink.orders.lock().unwrap().len(), 1);
+
+        // Повтор уведомления по переданному заказу не считается оплатой без заказа
+        handler.handle_payment(event).await.unwrap();
+        assert!(handler.processed_payments.contains(&payment_id));
+        assert_eq!(handler.sink.orders.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_order() {
+        let mut paid = handler(Some(FakePaymentProvider::default()));
+        let mut handler = handler(None);
+        order_to_ready(&mut handler).await;
+        *handler.sink.rejection.lock().unwrap() = Some(vec![FieldError {
+            field: "paper_size".to_string(),
+            message: "Формат недоступен".to_string(),
+        }]);
+        handler.handle_text_message(text("Готово")).await.unwrap();
+
+        // Отклоненный заказ остается у клиента для исправления
+        let order = handler.repository.get_order("79146795555@c.us").unwrap();
+        assert!(matches!(order.stage, OrderStage::Ready { .. }));
+        assert!(order.ready_at.is_none());
+        assert!(handler.sink.orders.lock().unwrap().is_empty());
+
+        *handler.sink.rejection.lock().unwrap() = None;
+        handler.handle_text_message(text("Готово")).await.unwrap();
+        assert!(handler.repository.get_order("79146795555@c.us").is_none());
+        assert_eq!(handler.sink.orders.lock().unwrap().len(), 1);
+
+        // Оплаченный заказ клиент изменить не может
+        order_to_ready(&mut paid).await;
+        paid.handle_text_message(text("Готово")).await.unwrap();
+        let order = paid.repository.get_order("79146795555@c.us").unwrap();
+        let payment_id = order.payment_id().unwrap().to_string();
+        *paid.sink.rejection.lock().unwrap() = Some(vec![FieldError {
+            field: "paper_size".to_string(),
+            message: "Формат недоступен".to_string(),
+        }]);
+        let payments = paid.payments.as_ref().unwrap();
+        payments.set_status(&payment_id, PaymentStatus::Succeeded);
+        paid
+            .handle_payment(PaymentEvent { payment_id })
+            .await
+            .unwrap();
+
+        // Оплаченный заказ не возвращается клиенту, его разбирает администратор
+        assert!(paid.repository.get_order("79146795555@c.us").is_none());
+        assert!(paid.retry.is_empty());
+        assert!(paid.sink.orders.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_payment_expired() {
+        let mut handler = handler(Some(FakePaymentProvider::default()));
+        order_to_ready(&mut handler).await;
+        handler.handle_text_message(text("Готово")).await.unwrap();
+        let mut order = handler.repository.get_order("79146795555@c.us").unwrap();
+        let payment_id = order.payment_id().unwrap().to_string();
+
+        // Ответа клиента ждут меньше, чем оплаты
+        order.last_msg_time -= Duration::from_secs(config().REPEAT_TIMEOUT * 10);
+        handler.repository.set_order(order.clone());
+        handler.handle_awaits().await.unwrap();
+        assert!(handler.repository.get_order("79146795555@c.us").is_some());
+
+        order.last_msg_time -= Duration::from_secs(config().PAYMENT_TIMEOUT);
+        handler.repository.set_order(order);
+        handler.handle_awaits().await.unwrap();
+        assert!(handler.repository.get_order("79146795555@c.us").is_none());
+        let payments = handler.payments.as_ref().unwrap();
+        assert_eq!(
+            payments.payment_status(&payment_id).await.unwrap(),
+            PaymentStatus::Canceled
+        );
+
+        // Оплата без заказа только передается администратору
+        payments.set_status(&payment_id, PaymentStatus::Succeeded);
+        handler
+            .handle_payment(PaymentEvent { payment_id })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_submit_retry() {
+        let mut handler = handler(None);
+        order_to_ready(&mut handler).await;
+        handler.sink.unavailable.store(true, Ordering::Relaxed);
+        handler.handle_text_message(text("Готово")).await.unwrap();
+        assert_eq!(handler.retry.len(), 1);
+
+        // До конца паузы заказ не повторяется, пауза растет с каждой попыткой
+        handler.handle_awaits().await.unwrap();
+        assert_eq!(handler.retry[0].attempts, 1);
+        handler.retry[0].next_at = Instant::now();
+        handler.handle_awaits().await.unwrap();
+        assert_eq!(handler.retry[0].attempts, 2);
+        assert!(handler.retry[0].next_at > Instant::now() + SUBMIT_RETRY_INTERVAL);
+
+        handler.sink.unavailable.store(false, Ordering::Relaxed);
+        handler.retry[0].next_at = Instant::now();
+        handler.handle_awaits().await.unwrap();
+        assert!(handler.retry.is_empty());
+        assert_eq!(handler.sink.orders.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_submit_gives_up() {
+        let mut refused = handler(None);
+        let mut handler = handler(None);
+        order_to_ready(&mut handler).await;
+        handler.sink.unavailable.store(true, Ordering::Relaxed);
+        handler.handle_text_message(text("Готово")).await.unwrap();
+        for _ in 1..MAX_SUBMIT_ATTEMPTS {
+            handler.retry[0].next_at = Instant::now();
+            handler.handle_awaits().await.unwrap();
+        }
+        assert!(handler.retry.is_empty());
+
+        // Окончательный отказ не повторяется
+        order_to_ready(&mut refused).await;
+        refused.sink.refused.store(true, Ordering::Relaxed);
+        refused.handle_text_message(text("Готово")).await.unwrap();
+        assert!(refused.retry.is_empty());
+        assert!(refused.repository.get_order("79146795555@c.us").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sales_log() {
+        let path =
+            std::env::temp_dir().join(format!("astrafoto-sales-audit-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut handler = handler(None).with_sales_log(SalesLog::new(AuditLog::new(&path)));
+        order_to_ready(&mut handler).await;
+        handler.handle_text_message(text("Готово")).await.unwrap();
+        for answer in ["Здравствуйте", "Отмена"] {
+            handler.handle_text_message(text(answer)).await.unwrap();
+        }
+
+        let today = Utc::now().date_naive();
+        let records = SalesLog::new(AuditLog::new(&path))
+            .read(today.pred_opt().unwrap(), today.succ_opt().unwrap(), chrono_tz::Tz::UTC)
+            .unwrap();
+        let report = DailyReport::new(today, &records);
+        assert_eq!(report.started, 2);
+        assert_eq!(report.submitted, 1);
+        assert_eq!(report.canceled, 1);
+        assert_eq!(report.photos, 1);
+        assert_eq!(report.conversion(), Some(50));
+        assert!(report.revenue.is_some());
+        assert_eq!(report.revenue, records[1].total);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_faq_without_order() {
+        let mut handler = handler(None);
+
+        for question in ["Сколько стоит печать?", "Помощь"] {
+            handler.handle_text_message(text(question)).await.unwrap();
+            assert!(handler.repository.get_order("79146795555@c.us").is_none());
+        }
+        handler
+            .handle_text_message(text("Здравствуйте"))
+            .await
+            .unwrap();
+        assert!(handler.repository.get_order("79146795555@c.us").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_change_order() {
+        let mut handler = handler(None);
+
+        for answer in ["Здравствуйте", "1", "1", "1", "2", "1"] {
+            handler.handle_text_message(text(answer)).await.unwrap();
+        }
+        let order = handler.repository.get_order("79146795555@c.us").unwrap();
+        assert!(matches!(order.stage, OrderStage::Ready { .. }));
+
+        handler.handle_text_message(text("Назад")).await.unwrap();
+        let order = handler.repository.get_order("79146795555@c.us").unwrap();
+        assert!(matches!(order.stage, OrderStage::FitRequested { .. }));
+
+        handler
+            .handle_text_message(text("Изменить бумагу"))
+            .await
+            .unwrap();
+        let order = handler.repository.get_order("79146795555@c.us").unwrap();
+        assert!(matches!(order.stage, OrderStage::OptionsRequested { .. }));
+        assert!(order.selections.is_empty());
+
+        handler.handle_text_message(text("2")).await.unwrap();
+        let order = handler.repository.get_order("79146795555@c.us").unwrap();
+        assert_eq!(order.selections[0].choice, "матовая");
+    }
+
+    #[tokio::test]
+    async fn test_borderless_skips_fit() {
+        let mut handler = handler(None);
+        for answer in ["Здравствуйте", "1", "1", "1", "1"] {
+            handler.handle_text_message(text(answer)).await.unwrap();
+        }
+        // Без полей фото всегда обрезается, вопрос о белых полях не задается
+        let order = handler.repository.get_order("79146795555@c.us").unwrap();
+        assert!(matches!(order.stage, OrderStage::Ready { .. }));
+        assert_eq!(order.fit(), Some(Fit::Crop));
+
+        handler.handle_text_message(text("Назад")).await.unwrap();
+        let order = handler.repository.get_order("79146795555@c.us").unwrap();
+        assert!(matches!(order.stage, OrderStage::OptionsRequested { .. }));
+        assert_eq!(order.selections.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_chat() {
+        let mut handler = handler(None);
+
+        handler.handle_text_message(text("Здравствуйте")).await.unwrap();
+        let failure = DeliveryFailure {
+            chat_id: "79146795555@c.us".to_string(),
+            error: Error::ApiBadRequest("Bad Request: message is too long".to_string()),
+        };
+        handler.handle_delivery_failure(failure).await.unwrap();
+        assert!(handler.repository.get_order("79146795555@c.us").is_some());
+        assert!(handler.unreachable.is_empty());
+
+        let failure = DeliveryFailure {
+            chat_id: "79146795555@c.us".to_string(),
+            error: Error::ChatUnreachable("Forbidden: bot was blocked by the user".to_string()),
+        };
+        handler.handle_delivery_failure(failure).await.unwrap();
+        assert!(handler.repository.get_order("79146795555@c.us").is_none());
+        assert!(handler.unreachable.contains("79146795555@c.us"));
+
+        handler.handle(Message::Text(text("1"))).await.unwrap();
+        assert!(handler.unreachable.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_channel_alerts() {
+        let mut handler = handler(None);
+        let state = Message::InstanceState("shop".to_string(), InstanceState::Blocked);
+        handler.handle(state).await.unwrap();
+        let failure = DeliveryFailure {
+            chat_id: "wa:79146795555@c.us".to_string(),
+            error: Error::ApiUnauthorized("401".to_string()),
+        };
+        handler.handle_delivery_failure(failure).await.unwrap();
+
+        // Оповещения ограничиваются для каждого канала отдельно
+        let mut channels: Vec<&String> = handler.last_instance_alert.keys().collect();
+        channels.sort();
+        assert_eq!(channels, vec!["shop", "wa"]);
+    }
 }
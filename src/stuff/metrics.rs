@@ -0,0 +1,206 @@
+use crate::stuff::data_types::{Message, Order};
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::encoding::text::encode;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{Histogram, exponential_buckets};
+use prometheus_client::registry::Registry;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct KindLabels {
+    kind: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct StageLabels {
+    stage: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ResultLabels {
+    result: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ActionLabels {
+    action: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ApiLabels {
+    api: &'static str,
+    method: &'static str,
+}
+
+type DurationFamily = Family<ApiLabels, Histogram, fn() -> Histogram>;
+
+/// Метрики бота в формате Prometheus, отдаются по GET /metrics
+pub struct Metrics {
+    registry: Registry,
+    messages_received: Family<KindLabels, Counter>,
+    orders: Family<StageLabels, Gauge>,
+    repository_size: Gauge,
+    orders_submitted: Family<ResultLabels, Counter>,
+    awaits: Family<ActionLabels, Counter>,
+    api_duration: DurationFamily,
+    api_errors: Family<ApiLabels, Counter>,
+}
+
+pub fn metrics() -> &'static Metrics {
+    static INSTANCE: OnceLock<Metrics> = OnceLock::new();
+    INSTANCE.get_or_init(Metrics::new)
+}
+
+/// Ответ Green API при long polling приходит до 20 секунд
+fn duration_histogram() -> Histogram {
+    Histogram::new(exponential_buckets(0.05, 2.0, 10))
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let mut metrics = Self {
+            registry: Registry::with_prefix("astrafoto"),
+            messages_received: Family::default(),
+            orders: Family::default(),
+            repository_size: Gauge::default(),
+            orders_submitted: Family::default(),
+            awaits: Family::default(),
+            api_duration: Family::new_with_constructor(duration_histogram),
+            api_errors: Family::default(),
+        };
+        let registry = &mut metrics.registry;
+        registry.register(
+            "messages_received",
+            "Входящие сообщения по типу",
+            metrics.messages_received.clone(),
+        );
+        registry.register(
+            "orders",
+            "Незавершенные заказы по этапу",
+            metrics.orders.clone(),
+        );
+        registry.register(
+            "repository_size",
+            "Заказов в репозитории",
+            metrics.repository_size.clone(),
+        );
+        registry.register(
+            "orders_submitted",
+            "Передача заказов в печать: ok, rejected, failed",
+            metrics.orders_submitted.clone(),
+        );
+        registry.register(
+            "awaits",
+            "Напоминания клиентам и отмены заказов по таймауту",
+            metrics.awaits.clone(),
+        );
+        registry.register(
+            "api_request_duration_seconds",
+            "Время ответа внешних API",
+            metrics.api_duration.clone(),
+        );
+        registry.register(
+            "api_errors",
+            "Ошибки запросов к внешним API",
+            metrics.api_errors.clone(),
+        );
+        metrics
+    }
+
+    /// Пустые ответы long polling не считаются
+    pub fn message_received(&self, message: &Message) {
+        if !matches!(message, Message::Empty) {
+            let kind = message.name();
+            self.messages_received
+                .get_or_create(&KindLabels { kind })
+                .inc();
+        }
+    }
+
+    pub fn order_submitted(&self, result: &'static str) {
+        self.orders_submitted
+            .get_or_create(&ResultLabels { result })
+            .inc();
+    }
+
+    /// `reminder` - клиенту повторно отправлен вопрос, `timeout` - заказ отменен
+    pub fn await_action(&self, action: &'static str) {
+        self.awaits.get_or_create(&ActionLabels { action }).inc();
+    }
+
+    /// Снимок репозитория, обновляется на каждом цикле опроса
+    pub fn set_orders<'o>(&self, orders: impl Iterator<Item = &'o Order>) {
+        let mut stages = HashMap::new();
+        for order in orders {
+            *stages.entry(order.stage.name()).or_insert(0) += 1;
+        }
+        self.repository_size.set(stages.values().sum());
+        self.orders.clear();
+        for (stage, count) in stages {
+            self.orders.get_or_create(&StageLabels { stage }).set(count);
+        }
+    }
+
+    /// Время и ошибки запроса к внешнему API. Ошибкой считается и ответ не 2xx
+    pub async fn timed(
+        &self,
+        api: &'static str,
+        method: &'static str,
+        request: reqwest::RequestBuilder,
+    ) -> reqwest::Result<reqwest::Response> {
+        let labels = ApiLabels { api, method };
+        let start = Instant::now();
+        let response = request.send().await;
+        self.api_duration
+            .get_or_create(&labels)
+            .observe(start.elapsed().as_secs_f64());
+        if !matches!(&response, Ok(r) if r.status().is_success()) {
+            self.api_errors.get_or_create(&labels).inc();
+        }
+        response
+    }
+
+    /// Текстовый формат OpenMetrics
+    pub fn encode(&self) -> String {
+        let mut output = String::new();
+        // Запись в String не завершается ошибкой
+        let _ = encode(&mut output, &self.registry);
+        output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stuff::data_types::ReceivedMessage;
+
+    #[test]
+    fn encode_test() {
+        let metrics = Metrics::new();
+        let text = Message::Text(ReceivedMessage {
+            chat_id: "79146795555@c.us".to_string(),
+            customer_name: "Andrey".to_string(),
+            message_id: None,
+            message: "Привет".to_string(),
+        });
+        metrics.message_received(&text);
+        metrics.message_received(&text);
+        metrics.message_received(&Message::Empty);
+        metrics.order_submitted("ok");
+        metrics.await_action("timeout");
+        let order = Order::new("79146795555@c.us".to_string(), "Andrey".to_string(), vec![]);
+        metrics.set_orders([&order].into_iter());
+
+        let output = metrics.encode();
+        assert!(output.contains("astrafoto_messages_received_total{kind=\"Text\"} 2\n"));
+        assert!(!output.contains("kind=\"Empty\""));
+        assert!(output.contains("astrafoto_orders_submitted_total{result=\"ok\"} 1\n"));
+        assert!(output.contains("astrafoto_awaits_total{action=\"timeout\"} 1\n"));
+        assert!(output.contains("astrafoto_orders{stage=\"ProductRequested\"} 1\n"));
+        assert!(output.contains("astrafoto_repository_size 1\n"));
+    }
+}
@@ -1,10 +1,35 @@
-pub mod paper;
+pub mod catalog;
+pub mod faq;
+pub mod money;
+pub mod image_info;
 
+pub mod http;
 pub mod transport;
+pub mod telegram;
+pub mod multi;
+pub mod worker;
+pub mod worker_api;
+pub mod worker_auth;
+pub mod sink;
+pub mod files;
+pub mod hot_folder;
+pub mod email;
+pub mod outbox;
 pub mod data_types;
 pub mod poller;
 pub mod error;
 pub mod message_handler;
 pub mod repository;
 pub mod prompt;
-mod wa_types;
\ No newline at end of file
+pub mod payment;
+pub mod schedule;
+pub mod production;
+pub mod server;
+pub mod alert;
+pub mod audit;
+pub mod health;
+pub mod logging;
+pub mod metrics;
+pub mod report;
+mod wa_types;
+mod tg_types;
\ No newline at end of file
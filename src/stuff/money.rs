@@ -0,0 +1,198 @@
+use crate::stuff::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Currency {
+    Rub,
+    Usd,
+    Eur,
+    Kzt,
+}
+
+impl Currency {
+    /// Количество знаков дробной части (копейки, центы)
+    pub fn minor_digits(&self) -> u32 {
+        2
+    }
+
+    fn minor_factor(&self) -> i64 {
+        10_i64.pow(self.minor_digits())
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Currency::Rub => "руб.",
+            Currency::Usd => "$",
+            Currency::Eur => "€",
+            Currency::Kzt => "₸",
+        }
+    }
+
+    fn symbol_before(&self) -> bool {
+        matches!(self, Currency::Usd)
+    }
+
+    fn decimal_separator(&self) -> char {
+        match self {
+            Currency::Usd => '.',
+            _ => ',',
+        }
+    }
+
+    fn group_separator(&self) -> &'static str {
+        match self {
+            Currency::Usd => ",",
+            _ => "\u{a0}",
+        }
+    }
+}
+
+/// Денежная сумма в минимальных единицах валюты (копейках)
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Money {
+    pub amount: i64,
+    pub currency: Currency,
+}
+
+impl Money {
+    pub fn new(amount: i64, currency: Currency) -> Self {
+        Self { amount, currency }
+    }
+
+    /// Разбирает сумму в основных единицах: "22", "22.5", "22,50"
+    pub fn parse(value: &str, currency: Currency) -> Result<Self> {
+        let invalid = || Error::MoneyInvalid(value.to_string());
+        let value = value.trim().replace(',', ".");
+        let (negative, value) = match value.strip_prefix('-') {
+            Some(v) => (true, v.to_string()),
+            None => (false, value),
+        };
+        let (major, minor) = value.split_once('.').unwrap_or((&value, ""));
+        let digits = currency.minor_digits() as usize;
+        if major.is_empty() || minor.len() > digits {
+            return Err(invalid());
+        }
+        let major: i64 = major.parse().map_err(|_| invalid())?;
+        let minor: i64 = if minor.is_empty() {
+            0
+        } else {
+            format!("{:0<digits$}", minor).parse().map_err(|_| invalid())?
+        };
+        let amount = major
+            .checked_mul(currency.minor_factor())
+            .and_then(|a| a.checked_add(minor))
+            .ok_or_else(invalid)?;
+        Ok(Self::new(if negative { -amount } else { amount }, currency))
+    }
+
+    /// Сумма в одной валюте, разные валюты не складываются
+    pub fn checked_add(self, rhs: Money) -> Result<Money> {
+        if self.currency != rhs.currency {
+            return Err(Error::CurrencyMismatch(self.currency, rhs.currency));
+        }
+        self.amount
+            .checked_add(rhs.amount)
+            .map(|amount| Money::new(amount, self.currency))
+            .ok_or_else(|| Error::MoneyInvalid(format!("{} + {}", self, rhs)))
+    }
+
+    /// Стоимость нескольких единиц, переполнение считается ошибкой
+    pub fn checked_mul(self, count: usize) -> Result<Money> {
+        i64::try_from(count)
+            .ok()
+            .and_then(|count| self.amount.checked_mul(count))
+            .map(|amount| Money::new(amount, self.currency))
+            .ok_or_else(|| Error::MoneyInvalid(format!("{} * {}", self, count)))
+    }
+
+    /// Сумма в основных единицах из числа с плавающей точкой: 1e5, 22.5
+    pub fn from_major(value: f64, currency: Currency) -> Result<Self> {
+        let invalid = || Error::MoneyInvalid(value.to_string());
+        let amount = value * currency.minor_factor() as f64;
+        let rounded = amount.round();
+        // i64::MAX as f64 округляется вверх, поэтому граница не включается
+        if !rounded.is_finite() || rounded.abs() >= i64::MAX as f64 {
+            return Err(invalid());
+        }
+        if (amount - rounded).abs() > 1e-6 {
+            return Err(invalid());
+        }
+        Ok(Self::new(rounded as i64, currency))
+    }
+}
+
+impl Display for Money {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let factor = self.currency.minor_factor();
+        let abs = self.amount.abs();
+        let major = (abs / factor).to_string();
+        let minor = abs % factor;
+
+        let mut grouped = String::new();
+        for (i, c) in major.chars().enumerate() {
+            if i > 0 && (major.len() - i).is_multiple_of(3) {
+                grouped.push_str(self.currency.group_separator());
+            }
+            grouped.push(c);
+        }
+        if minor != 0 {
+            let digits = self.currency.minor_digits() as usize;
+            grouped.push(self.currency.decimal_separator());
+            grouped.push_str(&format!("{:0digits$}", minor));
+        }
+
+        let sign = if self.amount < 0 { "-" } else { "" };
+        if self.currency.symbol_before() {
+            write!(f, "{sign}{}{grouped}", self.currency.symbol())
+        } else {
+            write!(f, "{sign}{grouped} {}", self.currency.symbol())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_money() {
+        assert_eq!(Money::parse("22", Currency::Rub).unwrap().amount, 2200);
+        assert_eq!(Money::parse("22.5", Currency::Rub).unwrap().amount, 2250);
+        assert_eq!(Money::parse("22,05", Currency::Rub).unwrap().amount, 2205);
+        assert_eq!(Money::parse("-3", Currency::Rub).unwrap().amount, -300);
+        assert!(Money::parse("22.505", Currency::Rub).is_err());
+        assert!(Money::parse("abc", Currency::Rub).is_err());
+        assert!(Money::parse("92233720368547758.08", Currency::Rub).is_err());
+        assert_eq!(
+            Money::from_major(1e5, Currency::Rub).unwrap().amount,
+            10000000
+        );
+        assert_eq!(Money::from_major(22.5, Currency::Rub).unwrap().amount, 2250);
+        assert!(Money::from_major(0.001, Currency::Rub).is_err());
+        assert!(Money::from_major(1e30, Currency::Rub).is_err());
+    }
+
+    #[test]
+    fn format_money() {
+        assert_eq!(Money::new(2200, Currency::Rub).to_string(), "22 руб.");
+        assert_eq!(Money::new(2250, Currency::Rub).to_string(), "22,50 руб.");
+        assert_eq!(
+            Money::new(125000, Currency::Rub).to_string(),
+            "1\u{a0}250 руб."
+        );
+        assert_eq!(Money::new(125005, Currency::Usd).to_string(), "$1,250.05");
+    }
+
+    #[test]
+    fn money_arithmetic() {
+        let price = Money::new(2250, Currency::Rub);
+        assert_eq!(price.checked_mul(3).unwrap().amount, 6750);
+        assert_eq!(price.checked_add(price).unwrap().amount, 4500);
+        assert!(price.checked_add(Money::new(100, Currency::Usd)).is_err());
+        let max = Money::new(i64::MAX, Currency::Rub);
+        assert!(max.checked_add(price).is_err());
+        assert!(max.checked_mul(2).is_err());
+    }
+}
@@ -0,0 +1,395 @@
+use crate::config::config;
+use crate::stuff::data_types::{InstanceState, Message};
+use crate::stuff::error::{Error, Result};
+use crate::stuff::health::backoff;
+use crate::stuff::telegram::{FILE_SCHEME, Telegram, TelegramFiles};
+use crate::stuff::transport::{Transport, WhatsApp};
+use log::{error, warn};
+use std::future::{Future, poll_fn};
+use std::pin::Pin;
+use std::task::Poll;
+use std::time::Duration;
+use tokio::sync::{Mutex, mpsc};
+use tokio::time::{sleep, timeout};
+
+/// Сколько `receive_message` ждет сообщения, прежде чем вернуть `Message::Empty`
+const RECEIVE_WAIT: Duration = Duration::from_secs(5);
+const MAX_CHANNEL_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Один канал приема заказов
+pub enum Channel {
+    WhatsApp(WhatsApp),
+    Telegram(Telegram),
+}
+
+impl Channel {
+    /// Канал из описания "имя=whatsapp:idInstance:token" или "имя=telegram:token".
+    /// Чат администратора передается каналу, если ADMIN_CHAT_ID начинается с его имени.
+    pub fn parse(client: &reqwest::Client, spec: &str) -> Result<(String, Channel)> {
+        let invalid = || Error::ChannelInvalid(spec.to_string());
+        let (name, spec) = spec.trim().split_once('=').ok_or_else(invalid)?;
+        let (kind, credentials) = spec.split_once(':').ok_or_else(invalid)?;
+        let name = name.trim();
+        if name.is_empty() || name.contains(':') {
+            return Err(invalid());
+        }
+        let admin_chat_id = split_chat_id(&config().ADMIN_CHAT_ID)
+            .filter(|(channel, _)| *channel == name)
+            .map(|(_, chat_id)| chat_id.to_string());
+        let channel = match kind {
+            "whatsapp" => {
+                let (id_instance, token) = credentials.split_once(':').ok_or_else(invalid)?;
+                Channel::WhatsApp(WhatsApp::with_instance(
+                    client.clone(),
+                    id_instance,
+                    token,
+                    admin_chat_id,
+                ))
+            }
+            "telegram" => Channel::Telegram(Telegram::with_api(
+                client.clone(),
+                &config().TELEGRAM_API_URL,
+                credentials,
+            )),
+            _ => return Err(invalid()),
+        };
+        Ok((name.to_string(), channel))
+    }
+}
+
+impl Transport for Channel {
+    async fn receive_message(&self) -> Result<Message> {
+        match self {
+            Channel::WhatsApp(t) => t.receive_message().await,
+            Channel::Telegram(t) => t.receive_message().await,
+        }
+    }
+
+    async fn send_message(&self, chat_id: String, msg: String) -> Result<()> {
+        match self {
+            Channel::WhatsApp(t) => t.send_message(chat_id, msg).await,
+            Channel::Telegram(t) => t.send_message(chat_id, msg).await,
+        }
+    }
+
+    async fn send_message_id(&self, chat_id: String, msg: String) -> Result<Option<String>> {
+        match self {
+            Channel::WhatsApp(t) => t.send_message_id(chat_id, msg).await,
+            Channel::Telegram(t) => t.send_message_id(chat_id, msg).await,
+        }
+    }
+
+    async fn instance_state(&self) -> Result<InstanceState> {
+        match self {
+            Channel::WhatsApp(t) => t.instance_state().await,
+            Channel::Telegram(t) => t.instance_state().await,
+        }
+    }
+}
+
+/// Несколько каналов в одном процессе. Идентификаторы чатов имеют вид
+/// "канал:чат", поэтому заказы разных каналов не пересекаются в репозитории,
+/// а ответ уходит через тот канал, откуда пришло сообщение.
+/// Каналы опрашиваются одновременно в [`MultiTransport::run`], поэтому
+/// недоступный канал не задерживает сообщения остальных.
+pub struct MultiTransport<T: Transport> {
+    channels: Vec<(String, T)>,
+    received_tx: mpsc::Sender<Message>,
+    received_rx: Mutex<mpsc::Receiver<Message>>,
+}
+
+impl MultiTransport<Channel> {
+    /// Каналы из CHANNELS через запятую
+    pub fn from_config(client: &reqwest::Client) -> Result<Self> {
+        let channels = config()
+            .CHANNELS
+            .split(',')
+            .filter(|spec| !spec.trim().is_empty())
+            .map(|spec| Channel::parse(client, spec))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(MultiTransport::new(channels))
+    }
+
+    /// Файлы ботов Telegram по имени канала
+    pub fn telegram_files(&self) -> Vec<(String, TelegramFiles)> {
+        self.channels
+            .iter()
+            .filter_map(|(name, channel)| match channel {
+                Channel::Telegram(t) => Some((name.clone(), t.files())),
+                Channel::WhatsApp(_) => None,
+            })
+            .collect()
+    }
+}
+
+impl<T: Transport> MultiTransport<T> {
+    pub fn new(channels: Vec<(String, T)>) -> Self {
+        let (received_tx, received_rx) = mpsc::channel(channels.len().max(1));
+        Self {
+            channels,
+            received_tx,
+            received_rx: Mutex::new(received_rx),
+        }
+    }
+
+    /// Опрашивает все каналы одновременно, пока работает процесс.
+    /// Транспорт заимствуется, поэтому каналы опрашиваются в одной задаче, а не в отдельных
+    pub async fn run(&self) {
+        let mut loops: Vec<Pin<Box<dyn Future<Output = ()> + '_>>> = self
+            .channels
+            .iter()
+            .map(|(name, channel)| Box::pin(self.poll_channel(name, channel)) as Pin<Box<_>>)
+            .collect();
+        poll_fn(|cx| {
+            for channel_loop in loops.iter_mut() {
+                let _ = channel_loop.as_mut().poll(cx);
+            }
+            Poll::<()>::Pending
+        })
+        .await
+    }
+
+    /// Ошибка канала откладывает только его следующий опрос
+    async fn poll_channel(&self, name: &str, channel: &T) {
+        let mut failures = 0;
+        loop {
+            match channel.receive_message().await {
+                Ok(Message::Empty) => failures = 0,
+                Ok(message) => {
+                    failures = 0;
+                    // Получатель принадлежит транспорту и не закрывается раньше него
+                    let _ = self.received_tx.send(qualify_message(name, message)).await;
+                }
+                Err(e) => {
+                    failures += 1;
+                    let delay = backoff(MAX_CHANNEL_BACKOFF, failures);
+                    error!(
+                        "[receive_message] channel {}: {}, retry in {:?}",
+                        name, e, delay
+                    );
+                    sleep(delay).await;
+                }
+            }
+        }
+    }
+
+    fn channel<'c>(&self, chat_id: &'c str) -> Result<(&'c str, &T)> {
+        split_chat_id(chat_id)
+            .and_then(|(name, chat_id)| {
+                self.channels
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .map(|(_, channel)| (chat_id, channel))
+            })
+            .ok_or_else(|| Error::ChannelNotFound(chat_id.to_string()))
+    }
+}
+
+pub fn qualify(channel: &str, chat_id: &str) -> String {
+    format!("{}:{}", channel, chat_id)
+}
+
+/// Имя канала и идентификатор чата в нем
+pub fn split_chat_id(chat_id: &str) -> Option<(&str, &str)> {
+    chat_id.split_once(':')
+}
+
+fn qualify_message(channel: &str, message: Message) -> Message {
+    match message {
+        Message::Text(mut m) => {
+            m.chat_id = qualify(channel, &m.chat_id);
+            Message::Text(m)
+        }
+        Message::Image(mut m) => {
+            m.chat_id = qualify(channel, &m.chat_id);
+            // file_id действует только для своего бота
+            if let Some(file_id) = m.file.url.strip_prefix(FILE_SCHEME) {
+                m.file.url = format!("{}{}", FILE_SCHEME, qualify(channel, file_id));
+            }
+            Message::Image(m)
+        }
+        Message::Status(mut s) => {
+            s.chat_id = qualify(channel, &s.chat_id);
+            Message::Status(s)
+        }
+        Message::Call(chat_id) => Message::Call(qualify(channel, &chat_id)),
+        Message::InstanceState(_, state) => {
+            warn!("Channel {} state {:?}", channel, state);
+            Message::InstanceState(channel.to_string(), state)
+        }
+        Message::Empty => Message::Empty,
+    }
+}
+
+impl<T: Transport> Transport for MultiTransport<T> {
+    /// Следующее сообщение из любого канала, полученное в `run`
+    async fn receive_message(&self) -> Result<Message> {
+        let mut received = self.received_rx.lock().await;
+        match timeout(RECEIVE_WAIT, received.recv()).await {
+            Ok(Some(message)) => Ok(message),
+            _ => Ok(Message::Empty),
+        }
+    }
+
+    async fn send_message(&self, chat_id: String, msg: String) -> Result<()> {
+        let (chat_id, channel) = self.channel(&chat_id)?;
+        channel.send_message(chat_id.to_string(), msg).await
+    }
+
+    async fn send_message_id(&self, chat_id: String, msg: String) -> Result<Option<String>> {
+        let (chat_id, channel) = self.channel(&chat_id)?;
+        channel.send_message_id(chat_id.to_string(), msg).await
+    }
+
+    /// Первое проблемное состояние среди каналов. Ошибка возвращается,
+    /// только если не ответил ни один канал
+    async fn instance_state(&self) -> Result<InstanceState> {
+        let mut failure = None;
+        let mut answered = false;
+        for (name, state) in self.channel_states().await {
+            match state {
+                Ok(InstanceState::Authorized) => answered = true,
+                Ok(state) => {
+                    warn!("Channel {} state {:?}", name, state);
+                    return Ok(state);
+                }
+                Err(e) => {
+                    warn!("Channel {} state unknown: {}", name, e);
+                    failure = Some(e);
+                }
+            }
+        }
+        match failure {
+            Some(e) if !answered => Err(e),
+            _ => Ok(InstanceState::Authorized),
+        }
+    }
+
+    async fn channel_states(&self) -> Vec<(String, Result<InstanceState>)> {
+        let mut states = vec![];
+        for (name, channel) in &self.channels {
+            states.push((name.clone(), channel.instance_state().await));
+        }
+        states
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stuff::data_types::ReceivedMessage;
+
+    /// Канал, который всегда присылает текст из своего чата и запоминает ответы.
+    /// Сломанный канал на любой запрос отвечает ошибкой
+    #[derive(Default)]
+    struct EchoChannel {
+        sent: std::sync::Mutex<Vec<String>>,
+        broken: bool,
+    }
+
+    impl EchoChannel {
+        fn broken() -> Self {
+            Self {
+                broken: true,
+                ..Default::default()
+            }
+        }
+    }
+
+    fn unavailable() -> Error {
+        Error::ApiUnavailable(reqwest::StatusCode::BAD_GATEWAY, String::new())
+    }
+
+    impl Transport for EchoChannel {
+        async fn receive_message(&self) -> Result<Message> {
+            if self.broken {
+                return Err(unavailable());
+            }
+            Ok(Message::Text(ReceivedMessage {
+                chat_id: "1".to_string(),
+                customer_name: String::new(),
+                message_id: None,
+                message: "Привет".to_string(),
+            }))
+        }
+
+        async fn send_message(&self, chat_id: String, _msg: String) -> Result<()> {
+            self.sent.lock().unwrap().push(chat_id);
+            Ok(())
+        }
+
+        async fn instance_state(&self) -> Result<InstanceState> {
+            match self.broken {
+                true => Err(unavailable()),
+                false => Ok(InstanceState::Authorized),
+            }
+        }
+    }
+
+    /// Чаты первых `count` сообщений, полученных транспортом
+    async fn receive_chats(multi: &MultiTransport<EchoChannel>, count: usize) -> Vec<String> {
+        let receive = async {
+            let mut chats = vec![];
+            while chats.len() < count {
+                if let Message::Text(m) = multi.receive_message().await.unwrap() {
+                    chats.push(m.chat_id);
+                }
+            }
+            chats
+        };
+        tokio::select! {
+            chats = receive => chats,
+            _ = multi.run() => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn route_by_channel() {
+        let multi = MultiTransport::new(vec![
+            ("wa".to_string(), EchoChannel::default()),
+            ("tg".to_string(), EchoChannel::default()),
+        ]);
+        let chats = receive_chats(&multi, 4).await;
+        assert!(chats.contains(&"wa:1".to_string()));
+        assert!(chats.contains(&"tg:1".to_string()));
+
+        multi
+            .send_message("tg:1".to_string(), "ok".to_string())
+            .await
+            .unwrap();
+        assert!(multi.channels[0].1.sent.lock().unwrap().is_empty());
+        assert_eq!(*multi.channels[1].1.sent.lock().unwrap(), ["1"]);
+        let unknown = multi
+            .send_message("vk:1".to_string(), "ok".to_string())
+            .await;
+        assert!(matches!(unknown, Err(Error::ChannelNotFound(_))));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn broken_channel_does_not_stall() {
+        let multi = MultiTransport::new(vec![
+            ("tg".to_string(), EchoChannel::broken()),
+            ("wa".to_string(), EchoChannel::default()),
+        ]);
+        assert_eq!(receive_chats(&multi, 3).await, ["wa:1", "wa:1", "wa:1"]);
+
+        let states = multi.channel_states().await;
+        assert!(matches!(&states[0], (name, Err(_)) if name == "tg"));
+        assert!(matches!(&states[1], (name, Ok(InstanceState::Authorized)) if name == "wa"));
+        assert_eq!(
+            multi.instance_state().await.unwrap(),
+            InstanceState::Authorized
+        );
+    }
+
+    #[test]
+    fn parse_channel_test() {
+        let client = reqwest::Client::new();
+        let (name, channel) = Channel::parse(&client, "tg=telegram:123:ABC").unwrap();
+        assert_eq!(name, "tg");
+        assert!(matches!(channel, Channel::Telegram(_)));
+        assert!(Channel::parse(&client, "wa2=whatsapp:1101000002:token").is_ok());
+        assert!(Channel::parse(&client, "wa=whatsapp:token").is_err());
+        assert!(Channel::parse(&client, "vk=vkontakte:token").is_err());
+    }
+}
@@ -0,0 +1,351 @@
+use crate::config::config;
+use crate::stuff::data_types::{InstanceState, Message};
+use crate::stuff::error::{Error, Result};
+use crate::stuff::transport::Transport;
+use log::{error, warn};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::time::{Instant, sleep_until};
+
+/// Ограничения частоты отправки сообщений
+#[derive(Debug, Clone, Copy)]
+pub struct OutboxLimits {
+    /// Минимальный интервал между любыми сообщениями
+    pub global_interval: Duration,
+    /// Минимальный интервал между сообщениями в один чат
+    pub chat_interval: Duration,
+    /// Задержка перед первым повтором, далее удваивается
+    pub retry_delay: Duration,
+    pub max_retries: u32,
+}
+
+impl OutboxLimits {
+    pub fn from_config() -> Self {
+        Self {
+            global_interval: Duration::from_millis(config().OUTBOX_GLOBAL_INTERVAL_MS),
+            chat_interval: Duration::from_millis(config().OUTBOX_CHAT_INTERVAL_MS),
+            retry_delay: Duration::from_millis(config().OUTBOX_RETRY_MS),
+            max_retries: config().OUTBOX_MAX_RETRIES,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Outgoing {
+    chat_id: String,
+    text: String,
+}
+
+/// Сообщение не доставлено и повторов больше не будет
+#[derive(Debug)]
+pub struct DeliveryFailure {
+    pub chat_id: String,
+    pub error: Error,
+}
+
+struct Pending {
+    text: String,
+    attempts: u32,
+}
+
+/// Транспорт, который ставит исходящие сообщения в очередь [`Outbox`]
+/// вместо немедленной отправки. Прием сообщений идет напрямую.
+pub struct QueuedTransport<'a, T: Transport> {
+    inner: &'a T,
+    queue: UnboundedSender<Outgoing>,
+}
+
+impl<T: Transport> Transport for QueuedTransport<'_, T> {
+    async fn receive_message(&self) -> Result<Message> {
+        self.inner.receive_message().await
+    }
+
+    async fn send_message(&self, chat_id: String, msg: String) -> Result<()> {
+        let outgoing = Outgoing { chat_id, text: msg };
+        if let Err(e) = self.queue.send(outgoing) {
+            error!("Outbox closed, message to {} dropped", e.0.chat_id);
+        }
+        Ok(())
+    }
+
+    async fn instance_state(&self) -> Result<InstanceState> {
+        self.inner.instance_state().await
+    }
+
+    async fn channel_states(&self) -> Vec<(String, Result<InstanceState>)> {
+        self.inner.channel_states().await
+    }
+}
+
+/// Очередь исходящих сообщений. Сообщения в один чат уходят строго по порядку,
+/// чаты обслуживаются по очереди с соблюдением [`OutboxLimits`].
+pub struct Outbox<'a, T: Transport> {
+    transport: &'a T,
+    incoming: UnboundedReceiver<Outgoing>,
+    limits: OutboxLimits,
+    chats: HashMap<String, VecDeque<Pending>>,
+    /// Чаты с неотправленными сообщениями в порядке обслуживания
+    turn: VecDeque<String>,
+    chat_ready: HashMap<String, Instant>,
+    global_ready: Instant,
+    failures: Option<UnboundedSender<DeliveryFailure>>,
+}
+
+impl<'a, T: Transport> Outbox<'a, T> {
+    pub fn new(transport: &'a T, limits: OutboxLimits) -> (Self, QueuedTransport<'a, T>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let outbox = Self {
+            transport,
+            incoming: rx,
+            limits,
+            chats: HashMap::new(),
+            turn: VecDeque::new(),
+            chat_ready: HashMap::new(),
+            global_ready: Instant::now(),
+            failures: None,
+        };
+        let queued = QueuedTransport {
+            inner: transport,
+            queue: tx,
+        };
+        (outbox, queued)
+    }
+
+    /// Канал, в который попадают окончательно недоставленные сообщения
+    pub fn delivery_failures(&mut self) -> UnboundedReceiver<DeliveryFailure> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.failures = Some(tx);
+        rx
+    }
+
+    /// Отправляет сообщения, пока жив хотя бы один [`QueuedTransport`]
+    pub async fn run(mut self) {
+        loop {
+            while let Ok(outgoing) = self.incoming.try_recv() {
+                self.push(outgoing);
+            }
+            let Some((chat_id, ready_at)) = self.next_chat() else {
+                match self.incoming.recv().await {
+                    Some(outgoing) => self.push(outgoing),
+                    None => return,
+                }
+                continue;
+            };
+            if ready_at > Instant::now() {
+                tokio::select! {
+                    _ = sleep_until(ready_at) => {}
+                    received = self.incoming.recv() => match received {
+                        Some(outgoing) => self.push(outgoing),
+                        None => sleep_until(ready_at).await,
+                    },
+                }
+                continue;
+            }
+            self.send_next(chat_id).await;
+        }
+    }
+
+    fn push(&mut self, outgoing: Outgoing) {
+        let queue = self.chats.entry(outgoing.chat_id.clone()).or_default();
+        if queue.is_empty() {
+            self.turn.push_back(outgoing.chat_id);
+        }
+        queue.push_back(Pending {
+            text: outgoing.text,
+            attempts: 0,
+        });
+    }
+
+    /// Чат, сообщение в который можно отправить раньше всех
+    fn next_chat(&self) -> Option<(String, Instant)> {
+        self.turn
+            .iter()
+            .map(|chat_id| {
+                let chat_ready = self.chat_ready.get(chat_id).copied();
+                let ready_at = chat_ready.map_or(self.global_ready, |r| r.max(self.global_ready));
+                (chat_id, ready_at)
+            })
+            .min_by_key(|(_, ready_at)| *ready_at)
+            .map(|(chat_id, ready_at)| (chat_id.clone(), ready_at))
+    }
+
+    async fn send_next(&mut self, chat_id: String) {
+        let Some(pending) = self.chats.get_mut(&chat_id).and_then(|q| q.front_mut()) else {
+            return;
+        };
+        let res = self
+            .transport
+            .send_message(chat_id.clone(), pending.text.clone())
+            .await;
+        let now = Instant::now();
+        self.global_ready = now + self.limits.global_interval;
+        self.chat_ready
+            .insert(chat_id.clone(), now + self.limits.chat_interval);
+
+        match res {
+            Err(e) if e.is_retryable() && pending.attempts < self.limits.max_retries => {
+                let delay = self.limits.retry_delay * 2u32.pow(pending.attempts);
+                pending.attempts += 1;
+                warn!(
+                    "Message to {} failed, retry {} in {:?}: {}",
+                    chat_id, pending.attempts, delay, e
+                );
+                self.chat_ready.insert(chat_id.clone(), now + delay);
+                if e.is_rate_limited() {
+                    self.global_ready = self.global_ready.max(now + delay);
+                }
+                // Повтор в конце очереди, чтобы не задерживать другие чаты
+                self.rotate(&chat_id, false);
+            }
+            res => {
+                if let Err(error) = res {
+                    error!("Message to {} dropped: {}", chat_id, error);
+                    if let Some(failures) = &self.failures {
+                        let failure = DeliveryFailure {
+                            chat_id: chat_id.clone(),
+                            error,
+                        };
+                        let _ = failures.send(failure);
+                    }
+                }
+                let queue = self.chats.get_mut(&chat_id);
+                let empty = queue.is_some_and(|q| {
+                    q.pop_front();
+                    q.is_empty()
+                });
+                self.rotate(&chat_id, empty);
+            }
+        }
+    }
+
+    /// Переносит чат в конец очереди обслуживания или убирает его
+    fn rotate(&mut self, chat_id: &str, remove: bool) {
+        self.turn.retain(|c| c != chat_id);
+        if remove {
+            self.chats.remove(chat_id);
+            // Интервал для чата соблюдается и после опустошения его очереди
+            let now = Instant::now();
+            self.chat_ready.retain(|_, ready| *ready > now);
+        } else {
+            self.turn.push_back(chat_id.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Запоминает отправленные сообщения, первые `failures` отправок отвечает 429
+    #[derive(Default)]
+    struct RecordingTransport {
+        sent: Mutex<Vec<(String, String, Instant)>>,
+        failures: Mutex<u32>,
+    }
+
+    impl Transport for RecordingTransport {
+        async fn receive_message(&self) -> Result<Message> {
+            Ok(Message::Empty)
+        }
+
+        async fn send_message(&self, chat_id: String, msg: String) -> Result<()> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err(Error::ApiRateLimited(String::new()));
+            }
+            self.sent
+                .lock()
+                .unwrap()
+                .push((chat_id, msg, Instant::now()));
+            Ok(())
+        }
+
+        async fn instance_state(&self) -> Result<InstanceState> {
+            Ok(InstanceState::Authorized)
+        }
+    }
+
+    fn limits() -> OutboxLimits {
+        OutboxLimits {
+            global_interval: Duration::from_millis(100),
+            chat_interval: Duration::from_millis(1000),
+            retry_delay: Duration::from_millis(500),
+            max_retries: 2,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limits_and_order() {
+        let transport = RecordingTransport::default();
+        let (outbox, queued) = Outbox::new(&transport, limits());
+        for (chat, text) in [("a", "1"), ("a", "2"), ("b", "1"), ("a", "3")] {
+            queued
+                .send_message(chat.to_string(), text.to_string())
+                .await
+                .unwrap();
+        }
+        drop(queued);
+        let start = Instant::now();
+        outbox.run().await;
+
+        let sent = transport.sent.lock().unwrap();
+        let texts: Vec<(&str, &str)> = sent
+            .iter()
+            .map(|(c, t, _)| (c.as_str(), t.as_str()))
+            .collect();
+        assert_eq!(texts, [("a", "1"), ("b", "1"), ("a", "2"), ("a", "3")]);
+        let offsets: Vec<u128> = sent
+            .iter()
+            .map(|(_, _, at)| (*at - start).as_millis())
+            .collect();
+        assert_eq!(offsets, [0, 100, 1000, 2000]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_on_rate_limit() {
+        let transport = RecordingTransport::default();
+        *transport.failures.lock().unwrap() = 2;
+        let (outbox, queued) = Outbox::new(&transport, limits());
+        for text in ["1", "2"] {
+            queued
+                .send_message("a".to_string(), text.to_string())
+                .await
+                .unwrap();
+        }
+        drop(queued);
+        let start = Instant::now();
+        outbox.run().await;
+
+        let sent = transport.sent.lock().unwrap();
+        let texts: Vec<&str> = sent.iter().map(|(_, t, _)| t.as_str()).collect();
+        assert_eq!(texts, ["1", "2"]);
+        // Повторы через 500 и 1000 мс
+        assert_eq!((sent[0].2 - start).as_millis(), 1500);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn report_failure_after_retries() {
+        let transport = RecordingTransport::default();
+        *transport.failures.lock().unwrap() = 3;
+        let (mut outbox, queued) = Outbox::new(&transport, limits());
+        let mut failures = outbox.delivery_failures();
+        for text in ["1", "2"] {
+            queued
+                .send_message("a".to_string(), text.to_string())
+                .await
+                .unwrap();
+        }
+        drop(queued);
+        outbox.run().await;
+
+        let failure = failures.try_recv().unwrap();
+        assert_eq!(failure.chat_id, "a");
+        assert!(failure.error.is_rate_limited());
+        let sent = transport.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].1, "2");
+    }
+}
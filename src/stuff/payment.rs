@@ -0,0 +1,174 @@
+use crate::config::config;
+use crate::stuff::data_types::Order;
+use crate::stuff::error::{Error, Result};
+use crate::stuff::money::Money;
+use log::error;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+pub trait PaymentProvider {
+    async fn create_payment(&self, order: &Order) -> Result<Payment>;
+    async fn payment_status(&self, payment_id: &str) -> Result<PaymentStatus>;
+    /// Отмена неоплаченной ссылки, чтобы клиент не оплатил удаленный заказ
+    async fn cancel_payment(&self, payment_id: &str) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct Payment {
+    pub id: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaymentStatus {
+    Pending,
+    Succeeded,
+    Canceled,
+}
+
+/// Уведомление платежной системы, полученное через HTTP callback.
+/// Статус в уведомлении не проверяется, он запрашивается у провайдера заново.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PaymentEvent {
+    pub payment_id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CreatePayment {
+    amount: Money,
+    description: String,
+    order_ref: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PaymentState {
+    status: PaymentStatus,
+}
+
+pub struct HttpPaymentProvider {
+    client: reqwest::Client,
+    api_url: String,
+    token: String,
+}
+
+impl HttpPaymentProvider {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            api_url: config().PAYMENT_API_URL.to_owned(),
+            token: config().PAYMENT_API_TOKEN.to_owned(),
+        }
+    }
+}
+
+impl PaymentProvider for HttpPaymentProvider {
+    async fn create_payment(&self, order: &Order) -> Result<Payment> {
+        let amount = order
+            .total()
+            .ok_or_else(|| Error::PaymentFailed("order price is not selected".to_string()))?;
+        let body = CreatePayment {
+            amount,
+            description: format!("Печать фотографий, {} шт.", order.files_count()),
+            order_ref: order.chat_id.clone(),
+        };
+        let response = self
+            .client
+            .post(format!("{}/payments", self.api_url))
+            .bearer_auth(&self.token)
+            .json::<CreatePayment>(&body)
+            .send()
+            .await?;
+        match response.status() {
+            StatusCode::OK | StatusCode::CREATED => Ok(response.json::<Payment>().await?),
+            status => {
+                let text = response.text().await?;
+                error!("[create_payment] {} {}", status, text);
+                Err(Error::PaymentFailed(text))
+            }
+        }
+    }
+
+    async fn payment_status(&self, payment_id: &str) -> Result<PaymentStatus> {
+        let response = self
+            .client
+            .get(format!("{}/payments/{}", self.api_url, payment_id))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+        match response.status() {
+            StatusCode::OK => Ok(response.json::<PaymentState>().await?.status),
+            StatusCode::NOT_FOUND => Err(Error::PaymentNotFound(payment_id.to_string())),
+            status => {
+                let text = response.text().await?;
+                error!("[payment_status] {} {}", status, text);
+                Err(Error::PaymentFailed(text))
+            }
+        }
+    }
+
+    async fn cancel_payment(&self, payment_id: &str) -> Result<()> {
+        let response = self
+            .client
+            .post(format!("{}/payments/{}/cancel", self.api_url, payment_id))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+        match response.status() {
+            StatusCode::OK => Ok(()),
+            StatusCode::NOT_FOUND => Err(Error::PaymentNotFound(payment_id.to_string())),
+            status => {
+                let text = response.text().await?;
+                error!("[cancel_payment] {} {}", status, text);
+                Err(Error::PaymentFailed(text))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakePaymentProvider {
+    statuses: std::sync::Mutex<std::collections::HashMap<String, PaymentStatus>>,
+}
+
+#[cfg(test)]
+impl FakePaymentProvider {
+    pub fn set_status(&self, payment_id: &str, status: PaymentStatus) {
+        self.statuses
+            .lock()
+            .unwrap()
+            .insert(payment_id.to_string(), status);
+    }
+}
+
+#[cfg(test)]
+impl PaymentProvider for FakePaymentProvider {
+    async fn create_payment(&self, order: &Order) -> Result<Payment> {
+        let id = format!("pay-{}", order.chat_id);
+        self.set_status(&id, PaymentStatus::Pending);
+        Ok(Payment {
+            url: format!("http://localhost/pay/{}", id),
+            id,
+        })
+    }
+
+    async fn payment_status(&self, payment_id: &str) -> Result<PaymentStatus> {
+        self.statuses
+            .lock()
+            .unwrap()
+            .get(payment_id)
+            .copied()
+            .ok_or(Error::PaymentNotFound(payment_id.to_string()))
+    }
+
+    async fn cancel_payment(&self, payment_id: &str) -> Result<()> {
+        match self.payment_status(payment_id).await? {
+            PaymentStatus::Succeeded => Err(Error::PaymentFailed("already paid".to_string())),
+            _ => {
+                self.set_status(payment_id, PaymentStatus::Canceled);
+                Ok(())
+            }
+        }
+    }
+}
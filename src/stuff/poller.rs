@@ -1,7 +1,20 @@
-use log::info;
+use log::{error, info};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::sleep;
+use crate::stuff::data_types::Message;
 use crate::stuff::error::Result;
+use crate::stuff::health::backoff;
+use crate::stuff::logging;
 use crate::stuff::message_handler::MessageHandler;
+use crate::stuff::metrics::metrics;
+use crate::stuff::outbox::DeliveryFailure;
+use crate::stuff::payment::PaymentEvent;
 use crate::stuff::transport::Transport;
+use crate::stuff::worker_api::WorkerEvent;
+
+/// Максимальная пауза между попытками получить сообщения
+const MAX_RECEIVE_BACKOFF: Duration = Duration::from_secs(60);
 
 pub struct Poller<'a, T, H>
 where
@@ -10,6 +23,9 @@ where
 {
     transport: &'a T,
     handler: H,
+    payment_events: Option<UnboundedReceiver<PaymentEvent>>,
+    delivery_failures: Option<UnboundedReceiver<DeliveryFailure>>,
+    worker_events: Option<UnboundedReceiver<WorkerEvent>>,
 }
 impl<'a, T, H> Poller<'a, T, H>
 where
@@ -17,15 +33,95 @@ where
     H: MessageHandler,
 {
     pub fn new(transport: &'a T, handler: H) -> Poller<'a, T, H> {
-        Self { transport, handler }
+        Self {
+            transport,
+            handler,
+            payment_events: None,
+            delivery_failures: None,
+            worker_events: None,
+        }
     }
 
+    pub fn with_payment_events(mut self, events: UnboundedReceiver<PaymentEvent>) -> Self {
+        self.payment_events = Some(events);
+        self
+    }
+
+    pub fn with_delivery_failures(mut self, failures: UnboundedReceiver<DeliveryFailure>) -> Self {
+        self.delivery_failures = Some(failures);
+        self
+    }
+
+    pub fn with_worker_events(mut self, events: UnboundedReceiver<WorkerEvent>) -> Self {
+        self.worker_events = Some(events);
+        self
+    }
+
+    /// Ошибки не завершают опрос: при недоступности Green API
+    /// попытки повторяются с нарастающей паузой
     pub async fn start_polling(&mut self) -> Result<()> {
         info!("Start polling...");
+        let mut failures = 0;
         loop {
-            let msg = self.transport.receive_message().await?;
-            self.handler.handle(msg).await?;
-            self.handler.handle_awaits().await?;
+            match self.transport.receive_message().await {
+                Ok(msg) => {
+                    if failures > 0 {
+                        info!("Polling resumed after {} failures", failures);
+                        failures = 0;
+                    }
+                    metrics().message_received(&msg);
+                    let message_id = (!matches!(msg, Message::Empty)).then(logging::new_id);
+                    let handled = logging::scope(message_id, self.handler.handle(msg)).await;
+                    if let Err(e) = handled {
+                        error!("[handle] {}", e);
+                    }
+                }
+                Err(e) => {
+                    failures += 1;
+                    let delay = backoff(MAX_RECEIVE_BACKOFF, failures);
+                    error!("[receive_message] {}, retry in {:?}", e, delay);
+                    sleep(delay).await;
+                }
+            }
+            self.handle_payment_events().await;
+            self.handle_delivery_failures().await;
+            self.handle_worker_events().await;
+            if let Err(e) = logging::scope(None, self.handler.handle_awaits()).await {
+                error!("[handle_awaits] {}", e);
+            }
+        }
+    }
+
+    async fn handle_payment_events(&mut self) {
+        let Some(events) = self.payment_events.as_mut() else {
+            return;
+        };
+        while let Ok(event) = events.try_recv() {
+            if let Err(e) = logging::scope(None, self.handler.handle_payment(event)).await {
+                error!("[handle_payment_events] {}", e);
+            }
+        }
+    }
+
+    async fn handle_delivery_failures(&mut self) {
+        let Some(failures) = self.delivery_failures.as_mut() else {
+            return;
+        };
+        while let Ok(failure) = failures.try_recv() {
+            if let Err(e) = logging::scope(None, self.handler.handle_delivery_failure(failure)).await {
+                error!("[handle_delivery_failures] {}", e);
+            }
+        }
+    }
+
+    async fn handle_worker_events(&mut self) {
+        let Some(events) = self.worker_events.as_mut() else {
+            return;
+        };
+        while let Ok(event) = events.try_recv() {
+            if let Err(e) = logging::scope(None, self.handler.handle_worker_event(event)).await {
+                error!("[handle_worker_events] {}", e);
+            }
         }
     }
 }
@@ -33,16 +129,21 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::stuff::http::build_client;
     use crate::stuff::message_handler::Handler;
+    use crate::stuff::payment::HttpPaymentProvider;
     use crate::stuff::repository::OrderRepository;
     use crate::stuff::transport::WhatsApp;
+    use crate::stuff::worker::Worker;
 
     #[tokio::test]
     #[ignore]
     async fn test_poll() {
-        let transport = WhatsApp::new();
+        let client = build_client().unwrap();
+        let transport = WhatsApp::new(client.clone());
         let repo = OrderRepository::new();
-        let handler = Handler::new(repo, &transport);
+        let sink = Worker::new(client).unwrap();
+        let handler = Handler::<_, _, _, HttpPaymentProvider>::new(repo, &transport, sink, None);
         let res = Poller::new(&transport, handler).start_polling().await;
 
         if let Err(ref e) = res {
@@ -0,0 +1,155 @@
+use crate::config::config;
+use crate::stuff::data_types::Order;
+use crate::stuff::error::{Error, Result};
+use crate::stuff::schedule::Schedule;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+
+/// Производительность печати и очередь переданных в работу заказов.
+/// Время готовности считается в рабочих часах точки.
+#[derive(Debug, Clone)]
+pub struct Production {
+    /// Фото в час по типу бумаги или названию товара, в нижнем регистре
+    rates: HashMap<String, u32>,
+    default_rate: u32,
+    /// Подготовка заказа независимо от количества фото
+    setup: Duration,
+    /// Время готовности заказов, уже переданных в работу
+    queue: Vec<DateTime<Utc>>,
+}
+
+impl Production {
+    pub fn new() -> Self {
+        Production::parse(
+            &config().PRODUCTION_CAPACITY,
+            config().PRODUCTION_DEFAULT_RATE,
+            Duration::minutes(config().PRODUCTION_MINUTES),
+        )
+        .expect("production capacity invalid")
+    }
+
+    /// Производительность: "глянцевая:120, матовая:100, Фотомагнит:20"
+    pub fn parse(capacity: &str, default_rate: u32, setup: Duration) -> Result<Production> {
+        let rates = capacity
+            .split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(|c| {
+                let invalid = || Error::CapacityInvalid(c.to_string());
+                let (name, rate) = c.rsplit_once(':').ok_or_else(invalid)?;
+                let rate = rate.trim().parse::<u32>().map_err(|_| invalid())?;
+                match rate {
+                    0 => Err(invalid()),
+                    _ => Ok((name.trim().to_lowercase(), rate)),
+                }
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+        if default_rate == 0 {
+            return Err(Error::CapacityInvalid("default rate 0".to_string()));
+        }
+        Ok(Production {
+            rates,
+            default_rate,
+            setup,
+            queue: vec![],
+        })
+    }
+
+    /// Время работы над заказом без учета очереди
+    pub fn work_time(&self, order: &Order) -> Duration {
+        let kind = order
+            .selections
+            .iter()
+            .find(|s| s.key.as_deref() == Some("paper"))
+            .map(|s| s.choice.as_str())
+            .or(order.product());
+        let rate = kind
+            .and_then(|k| self.rates.get(&k.to_lowercase()))
+            .copied()
+            .unwrap_or(self.default_rate);
+        let photos = order.files_count() as u64;
+        self.setup + Duration::seconds((photos * 3600).div_ceil(rate as u64) as i64)
+    }
+
+    /// Время готовности нового заказа после всех заказов в очереди
+    pub fn estimate(
+        &mut self,
+        order: &Order,
+        schedule: &Schedule,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        self.queue.retain(|ready| *ready > now);
+        let start = self.queue.iter().max().copied().unwrap_or(now).max(now);
+        schedule
+            .add_working_time(start, self.work_time(order))
+            .map(|ready| ready.with_timezone(&Utc))
+    }
+
+    /// Учитывает заказ, переданный в работу
+    pub fn enqueue(&mut self, ready: DateTime<Utc>) {
+        self.queue.push(ready);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stuff::catalog::Selection;
+    use crate::stuff::data_types::OrderFile;
+    use crate::stuff::money::{Currency, Money};
+
+    fn order(paper: &str, photos: usize) -> Order {
+        let files = (0..photos)
+            .map(|i| OrderFile {
+                url: format!("{}.jpg", i),
+                dimensions: None,
+            })
+            .collect();
+        let mut order = Order::new("79146795555@c.us".to_string(), "Andrey".to_string(), files);
+        order.selections.push(Selection {
+            group: "Тип бумаги".to_string(),
+            key: Some("paper".to_string()),
+            choice: paper.to_string(),
+            price: Money::new(0, Currency::Rub),
+            fit: None,
+        });
+        order
+    }
+
+    #[test]
+    fn parse_test() {
+        assert!(Production::parse("глянцевая:120, матовая:100", 100, Duration::zero()).is_ok());
+        assert!(Production::parse("глянцевая", 100, Duration::zero()).is_err());
+        assert!(Production::parse("глянцевая:0", 100, Duration::zero()).is_err());
+        assert!(Production::parse("", 0, Duration::zero()).is_err());
+    }
+
+    #[test]
+    fn estimate_test() {
+        let schedule = Schedule::parse("UTC", "09:00-18:00", "").unwrap();
+        let mut production = Production::parse("Глянцевая:120", 60, Duration::minutes(10)).unwrap();
+        assert_eq!(
+            production.work_time(&order("глянцевая", 120)),
+            Duration::minutes(70)
+        );
+        assert_eq!(
+            production.work_time(&order("матовая", 30)),
+            Duration::minutes(40)
+        );
+
+        let now = DateTime::parse_from_rfc3339("2026-10-19T17:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let first = production
+            .estimate(&order("глянцевая", 120), &schedule, now)
+            .unwrap();
+        assert_eq!(first.to_rfc3339(), "2026-10-20T09:10:00+00:00");
+        production.enqueue(first);
+
+        // Второй заказ начнут делать после первого
+        let second = production
+            .estimate(&order("матовая", 30), &schedule, now)
+            .unwrap();
+        assert_eq!(second.to_rfc3339(), "2026-10-20T09:50:00+00:00");
+    }
+}
@@ -1,86 +1,489 @@
 use crate::config::config;
-use crate::stuff::paper::Paper;
+use crate::stuff::catalog::{Catalog, OptionGroup, Selection};
+use crate::stuff::data_types::{Fit, Order, OrderFile, OrderStage};
+use crate::stuff::faq::Faq;
+use crate::stuff::image_info::{format_ratio, print_ratio, ratio_matches};
+use crate::stuff::money::Money;
+use crate::stuff::schedule::Schedule;
+use crate::stuff::worker_api::FieldError;
+use chrono::DateTime;
+use chrono_tz::Tz;
 use std::fmt::Write;
 
 const READY: &str = "Если Вы загрузили все фотографии, то отправьте слово: Готово";
+const BACK: &str = "Чтобы вернуться к предыдущему шагу, отправьте слово: Назад";
+const CHANGE: &str = "Чтобы изменить параметры заказа, отправьте слово: Изменить";
+
+const HELP: &str = "Чтобы заказать печать, отправьте фотографии в этот чат.\n\nКоманды:\nГотово - передать заказ в работу\nНазад - вернуться к предыдущему шагу\nИзменить - изменить параметры заказа\nОтмена - отменить заказ\nПомощь - список команд";
 
 pub struct Prompt {
-    paper: Paper,
+    catalog: Catalog,
+    faq: Faq,
 }
 
 impl Prompt {
     pub fn new() -> Self {
-        let paper = Paper::new();
-        Self { paper }
+        let catalog = Catalog::new();
+        let faq = Faq::new();
+        Self { catalog, faq }
     }
 
-    pub fn try_get_paper(&self, idx: usize) -> Option<String> {
-        if idx >= self.paper.paper_vec().len() {
-            None
-        } else {
-            Some(self.paper.paper_vec()[idx].clone())
+    /// Товар выбирается автоматически, если в каталоге он один
+    pub fn default_product(&self) -> Option<String> {
+        match self.catalog.products() {
+            [product] => Some(product.name.clone()),
+            _ => None,
         }
     }
 
-    pub fn try_get_size(&self, paper: &str, idx: usize) -> Option<(String, i32)> {
-        let sizes = self.paper.sizes_by_paper(paper);
-        if idx >= sizes.len() {
-            None
-        } else {
-            Some((sizes[idx].size.clone(), sizes[idx].price))
+    pub fn try_get_product(&self, idx: usize) -> Option<String> {
+        self.catalog.products().get(idx).map(|p| p.name.clone())
+    }
+
+    pub fn try_get_option(
+        &self,
+        product: &str,
+        selections: &[Selection],
+        idx: usize,
+    ) -> Option<Selection> {
+        self.catalog.select(product, selections, idx)
+    }
+
+    /// Цена за штуку, если выбраны все варианты товара
+    pub fn options_price(&self, product: &str, selections: &[Selection]) -> Option<Money> {
+        match self.catalog.next_group(product, selections) {
+            Some(_) => None,
+            None => self.catalog.price(product, selections),
         }
     }
 
-    pub fn paper_prompt(&self) -> String {
-        self.paper.paper_vec().iter().enumerate().fold(
-            "Выберите тип бумаги: \n".to_string(),
-            |mut output, (idx, b)| {
-                let _ = writeln!(output, "{} - {}", idx + 1, b);
+    pub fn product_prompt(&self) -> String {
+        self.catalog.products().iter().enumerate().fold(
+            "Выберите товар: \n".to_string(),
+            |mut output, (idx, p)| {
+                let _ = writeln!(output, "{} - {}", idx + 1, p.name);
                 output
             },
         )
     }
 
-    pub fn size_prompt(&self, paper: &str) -> String {
-        let sizes = self.paper.sizes_by_paper(paper);
-        sizes.iter().enumerate().fold(
-            "Выберите размер фотографий: \n".to_string(),
-            |mut output, (idx, p)| {
-                let _ = writeln!(output, "{} - {} {}руб/шт", idx + 1, p.size, p.price);
+    pub fn option_prompt(&self, product: &str, selections: &[Selection]) -> String {
+        let Some(group) = self.catalog.next_group(product, selections) else {
+            return self.ready_prompt();
+        };
+        let current = self.catalog.price(product, selections);
+        let mut output = group.choices.iter().enumerate().fold(
+            format!("{}: \n", group.prompt),
+            |mut output, (idx, c)| {
+                let _ = match current {
+                    _ if c.price.amount == 0 => writeln!(output, "{} - {}", idx + 1, c.name),
+                    Some(p) if p.amount != 0 => {
+                        writeln!(output, "{} - {} +{}/шт", idx + 1, c.name, c.price)
+                    }
+                    _ => writeln!(output, "{} - {} {}/шт", idx + 1, c.name, c.price),
+                };
                 output
             },
+        );
+        if !selections.is_empty() || self.default_product().is_none() {
+            let _ = write!(output, "\n{}", BACK);
+        }
+        output
+    }
+
+    /// Что можно изменить в заказе и как это сделать
+    pub fn change_prompt(&self, selections: &[Selection], fit: Option<Fit>) -> String {
+        let mut output = "Что изменить? Отправьте, например:\n".to_string();
+        if self.default_product().is_none() {
+            let _ = writeln!(output, "Изменить товар");
+        }
+        for s in selections {
+            let _ = writeln!(
+                output,
+                "Изменить {} (сейчас: {})",
+                s.group.to_lowercase(),
+                s.choice
+            );
+        }
+        if let Some(fit) = fit {
+            let _ = writeln!(output, "Изменить кадрирование (сейчас: {})", fit);
+        }
+        let _ = write!(output, "{}", BACK);
+        output
+    }
+
+    /// Соотношение сторон выбранного формата печати
+    pub fn print_ratio(&self, selections: &[Selection]) -> Option<f64> {
+        selections
+            .iter()
+            .filter(|s| s.key.as_deref() == Some("size"))
+            .find_map(|s| print_ratio(&s.choice))
+    }
+
+    pub fn fit_prompt(&self, selections: &[Selection], files: &[OrderFile]) -> String {
+        let mut output = String::new();
+        if let Some(ratio) = self.print_ratio(selections) {
+            let _ = writeln!(
+                output,
+                "Соотношение сторон выбранного формата {}.",
+                format_ratio(ratio)
+            );
+            let ratios: Vec<f64> = files
+                .iter()
+                .filter_map(|f| f.dimensions.and_then(|d| d.ratio()))
+                .collect();
+            let mismatched: Vec<f64> = ratios
+                .iter()
+                .copied()
+                .filter(|r| !ratio_matches(*r, ratio))
+                .collect();
+            let _ = match mismatched.first() {
+                _ if ratios.is_empty() => writeln!(
+                    output,
+                    "Фото с телефона обычно имеют соотношение 4:3 или 19,5:9 и не совпадают с форматом печати."
+                ),
+                None => writeln!(output, "Полученные фото подходят под формат печати."),
+                Some(first) => writeln!(
+                    output,
+                    "Из полученных фото {} из {} имеют другое соотношение сторон (например {}).",
+                    mismatched.len(),
+                    ratios.len(),
+                    format_ratio(*first)
+                ),
+            };
+        }
+        let _ = write!(
+            output,
+            "Как печатать фото, если пропорции не совпадают? \n1 - Обрезать по формату (часть изображения по краям будет потеряна)\n2 - Вписать целиком с белыми полями\n\n{}",
+            BACK
+        );
+        output
+    }
+
+    /// Ответ на частый вопрос, если текст на него похож
+    pub fn faq_answer(&self, text: &str, schedule: &Schedule) -> Option<String> {
+        let entry = self.faq.find(text)?;
+        Some(
+            entry
+                .answer
+                .replace("{address}", &config().SHOP_ADDRESS)
+                .replace("{phone}", &config().SHOP_PHONE)
+                .replace("{prices}", &self.price_list())
+                .replace("{hours}", &schedule.hours_text()),
         )
     }
 
+    pub fn call_prompt(&self) -> String {
+        "Мы не принимаем звонки в WhatsApp. Напишите, пожалуйста, сообщение или отправьте фотографии в этот чат"
+            .to_owned()
+    }
+
+    pub fn help_prompt(&self) -> String {
+        HELP.to_owned()
+    }
+
+    /// Цены всех товаров с вариантами, влияющими на цену
+    pub fn price_list(&self) -> String {
+        let mut output = String::new();
+        for product in self.catalog.products() {
+            let _ = match product.price.amount {
+                0 => writeln!(output, "{}", product.name),
+                _ => writeln!(output, "{} {}/шт", product.name, product.price),
+            };
+            write_price_groups(&mut output, &product.options, 1);
+        }
+        output.trim_end().to_owned()
+    }
+
     pub fn ready_prompt(&self) -> String {
-        READY.to_owned()
+        format!("{}\n{}", READY, CHANGE)
+    }
+
+    pub fn payment_prompt(&self, url: &str, total: Money) -> String {
+        format!(
+            "Сумма заказа: {}\nДля оплаты перейдите по ссылке:\n{}\n\nЗаказ будет передан в работу после оплаты",
+            total, url
+        )
+    }
+
+    pub fn payment_failed_prompt(&self) -> String {
+        "Оплата не прошла. Чтобы получить новую ссылку на оплату, отправьте слово: Готово"
+            .to_owned()
+    }
+
+    pub fn final_prompt(&self, order_id: String, ready: Option<DateTime<Tz>>) -> String {
+        let mut output = format!("Ваш заказ {} принят!\n", order_id);
+        if let Some(ready) = ready {
+            let _ = writeln!(
+                output,
+                "Ориентировочное время готовности: {}",
+                format_time(ready)
+            );
+        }
+        let _ = write!(
+            output,
+            "\nПолучение по адресу:{}\nтел: {}",
+            config().SHOP_ADDRESS,
+            config().SHOP_PHONE
+        );
+        output
     }
 
-    pub fn final_prompt(&self, order_id: String) -> String {
+    pub fn pickup_prompt(&self, order_id: &str) -> String {
         format!(
-            "Ваш заказ {} принят!\n\nПолучение по адресу:{}\nтел: {}",
+            "Ваш заказ {} готов!\nПолучение по адресу:{}\nтел: {}",
             order_id,
             config().SHOP_ADDRESS,
             config().SHOP_PHONE
         )
     }
+
+    pub fn off_hours_prompt(&self, opening: Option<DateTime<Tz>>) -> String {
+        match opening {
+            Some(opening) => format!(
+                "Сейчас мы не работаем, откроемся {}. Вы можете оформить заказ, мы выполним его в рабочее время",
+                format_time(opening)
+            ),
+            None => "Сейчас мы не работаем. Вы можете оформить заказ, мы выполним его в рабочее время".to_owned(),
+        }
+    }
+
+    pub fn retry_prompt(&self) -> String {
+        "Сервис печати временно недоступен. Заказ сохранен и будет передан в работу автоматически"
+            .to_owned()
+    }
+
+    pub fn payment_expired_prompt(&self) -> String {
+        "Заказ отменен: срок действия ссылки на оплату истек".to_owned()
+    }
+
+    pub fn hold_prompt(&self, opening: Option<DateTime<Tz>>) -> String {
+        match opening {
+            Some(opening) => format!(
+                "Заказ сформирован и будет передан в работу после открытия {}",
+                format_time(opening)
+            ),
+            None => "Заказ сформирован и будет передан в работу после открытия".to_owned(),
+        }
+    }
+
+    /// Сервис печати указал, что исправить в заказе
+    pub fn rejected_prompt(&self, errors: &[FieldError]) -> String {
+        let mut output = "Заказ не принят, исправьте, пожалуйста:\n".to_owned();
+        write_field_errors(&mut output, errors);
+        let _ = write!(output, "\n{}", CHANGE);
+        output
+    }
+
+    /// Оплаченный заказ клиент не меняет, его разбирает администратор
+    pub fn paid_rejected_prompt(&self, errors: &[FieldError]) -> String {
+        let mut output = "Оплаченный заказ не может быть выполнен:\n".to_owned();
+        write_field_errors(&mut output, errors);
+        output.push_str("\nАдминистратор свяжется с Вами, чтобы изменить заказ или вернуть оплату");
+        output
+    }
+
+    /// Сводка для администратора по заказам всех каналов
+    pub fn orders_report(&self, orders: &[Order], held: &[Order]) -> String {
+        if orders.is_empty() && held.is_empty() {
+            return "Активных заказов нет".to_owned();
+        }
+        let mut output = format!("Оформляются: {}\n", orders.len());
+        for order in orders {
+            let _ = writeln!(
+                output,
+                "{} {}: {}, фото: {}",
+                order.chat_id,
+                order.customer_name,
+                stage_name(&order.stage),
+                order.files.len()
+            );
+        }
+        if !held.is_empty() {
+            let _ = writeln!(output, "\nОжидают открытия: {}", held.len());
+            for order in held {
+                let _ = writeln!(
+                    output,
+                    "{} {}: фото: {}",
+                    order.chat_id,
+                    order.customer_name,
+                    order.files.len()
+                );
+            }
+        }
+        output
+    }
+}
+
+fn write_field_errors(output: &mut String, errors: &[FieldError]) {
+    for e in errors {
+        let _ = match field_name(&e.field) {
+            Some(name) => writeln!(output, "- {}: {}", name, e.message),
+            None => writeln!(output, "- {}", e.message),
+        };
+    }
+}
+
+/// Название поля заказа в API сервиса печати для клиента
+fn field_name(field: &str) -> Option<&'static str> {
+    match field.split(['.', '[']).next().unwrap_or_default() {
+        "product" => Some("Товар"),
+        "paper_type" => Some("Бумага"),
+        "paper_size" => Some("Размер"),
+        "fit" => Some("Кадрирование"),
+        "files" => Some("Фото"),
+        _ => None,
+    }
+}
+
+fn stage_name(stage: &OrderStage) -> &'static str {
+    match stage {
+        OrderStage::ProductRequested => "выбор товара",
+        OrderStage::OptionsRequested { .. } => "выбор параметров",
+        OrderStage::FitRequested { .. } => "выбор кадрирования",
+        OrderStage::Ready { .. } => "ожидает подтверждения",
+        OrderStage::PaymentRequested { paid: false, .. } => "ожидает оплаты",
+        OrderStage::PaymentRequested { paid: true, .. } => "оплачен",
+    }
+}
+
+/// Время по часовому поясу точки: "20.10 в 09:00"
+fn format_time(time: DateTime<Tz>) -> String {
+    time.format("%d.%m в %H:%M").to_string()
+}
+
+fn write_price_groups(output: &mut String, groups: &[OptionGroup], depth: usize) {
+    for group in groups {
+        for c in &group.choices {
+            if c.price.amount == 0 && c.options.is_empty() {
+                continue;
+            }
+            let indent = "  ".repeat(depth);
+            let _ = match c.price.amount {
+                0 => writeln!(output, "{}{}", indent, c.name),
+                _ => writeln!(output, "{}{} - {}/шт", indent, c.name, c.price),
+            };
+            write_price_groups(output, &c.options, depth + 1);
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     #[test]
-    fn paper_prompt() {
+    fn product_prompt() {
+        let prompt = Prompt::new();
+        let prompt_str = prompt.product_prompt();
+        println!("{}", prompt_str);
+        assert!(!prompt_str.is_empty());
+    }
+    #[test]
+    fn option_prompt() {
+        let prompt = Prompt::new();
+        let paper = prompt.try_get_option("Фотопечать", &[], 0).unwrap();
+        let prompt_str = prompt.option_prompt("Фотопечать", &[paper]);
+        println!("{}", prompt_str);
+        assert!(prompt_str.contains("10x15 22 руб./шт"));
+        assert!(prompt_str.contains("Назад"));
+    }
+    #[test]
+    fn faq_answer() {
+        let prompt = Prompt::new();
+        let schedule = Schedule::new();
+        let answer = prompt.faq_answer("Сколько стоит печать?", &schedule).unwrap();
+        println!("{}", answer);
+        assert!(answer.contains("10x15 - 22 руб./шт"));
+        let answer = prompt.faq_answer("Какой у вас адрес?", &schedule).unwrap();
+        assert!(answer.contains(&config().SHOP_ADDRESS));
+        let answer = prompt.faq_answer("Когда работаете?", &schedule).unwrap();
+        assert!(answer.contains(&schedule.hours_text()));
+    }
+    #[test]
+    fn final_prompt() {
+        use chrono::TimeZone;
+        let prompt = Prompt::new();
+        let ready = chrono_tz::Asia::Vladivostok
+            .with_ymd_and_hms(2026, 10, 20, 9, 30, 0)
+            .unwrap();
+        let prompt_str = prompt.final_prompt("42".to_string(), Some(ready));
+        println!("{}", prompt_str);
+        assert!(prompt_str.contains("20.10 в 09:30"));
+        assert!(prompt_str.contains(&config().SHOP_ADDRESS));
+    }
+    #[test]
+    fn change_prompt() {
         let prompt = Prompt::new();
-        let prompt_str = prompt.paper_prompt();
+        let paper = prompt.try_get_option("Фотопечать", &[], 1).unwrap();
+        let prompt_str = prompt.change_prompt(&[paper], Some(Fit::Crop));
         println!("{}", prompt_str);
-        assert!(prompt_str.len() > 0);
+        assert!(prompt_str.contains("Изменить тип бумаги (сейчас: матовая)"));
+        assert!(prompt_str.contains("Изменить кадрирование"));
     }
     #[test]
-    fn sizes_prompt() {
+    fn fit_prompt() {
+        use crate::stuff::image_info::Dimensions;
         let prompt = Prompt::new();
-        let prompt_str = prompt.size_prompt("глянцевая");
+        let paper = prompt.try_get_option("Фотопечать", &[], 0).unwrap();
+        let size = prompt
+            .try_get_option("Фотопечать", std::slice::from_ref(&paper), 0)
+            .unwrap();
+        let files = vec![
+            OrderFile {
+                url: "1.jpg".to_string(),
+                dimensions: Some(Dimensions {
+                    width: 4032,
+                    height: 3024,
+                }),
+            },
+            OrderFile {
+                url: "2.jpg".to_string(),
+                dimensions: Some(Dimensions {
+                    width: 1000,
+                    height: 1500,
+                }),
+            },
+        ];
+        let prompt_str = prompt.fit_prompt(&[paper, size], &files);
         println!("{}", prompt_str);
-        assert!(prompt_str.len() > 0);
+        assert!(prompt_str.contains("3:2"));
+        assert!(prompt_str.contains("1 из 2"));
+        assert!(prompt_str.contains("4:3"));
+    }
+    #[test]
+    fn rejected_prompt() {
+        let prompt = Prompt::new();
+        let errors = [
+            FieldError {
+                field: "paper_size".to_string(),
+                message: "Формат 30x40 недоступен".to_string(),
+            },
+            FieldError {
+                field: "comment".to_string(),
+                message: "Слишком длинный комментарий".to_string(),
+            },
+        ];
+        let prompt_str = prompt.rejected_prompt(&errors);
+        assert!(prompt_str.contains("- Размер: Формат 30x40 недоступен\n"));
+        assert!(prompt_str.contains("- Слишком длинный комментарий\n"));
+        assert!(prompt_str.contains("Изменить"));
+        let prompt_str = prompt.paid_rejected_prompt(&errors);
+        assert!(prompt_str.contains("- Размер: Формат 30x40 недоступен\n"));
+        assert!(!prompt_str.contains("Изменить"));
+    }
+    #[test]
+    fn orders_report() {
+        let prompt = Prompt::new();
+        assert_eq!(prompt.orders_report(&[], &[]), "Активных заказов нет");
+        let order = Order::from_txt_msg(crate::stuff::data_types::ReceivedMessage {
+            chat_id: "tg:42".to_string(),
+            customer_name: "Иван".to_string(),
+            message_id: None,
+            message: "Привет".to_string(),
+        });
+        let orders = [order];
+        let report = prompt.orders_report(&orders, &orders);
+        assert!(report.contains("tg:42 Иван: выбор товара, фото: 0"));
+        assert!(report.contains("Ожидают открытия: 1"));
     }
 }
@@ -0,0 +1,457 @@
+use crate::config::config;
+use crate::stuff::audit::{AuditEntry, AuditLog};
+use crate::stuff::data_types::Order;
+use crate::stuff::error::{Error, Result};
+use crate::stuff::money::Money;
+use crate::stuff::schedule::Schedule;
+use crate::stuff::transport::Transport;
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+/// Что произошло с заказом
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SaleEvent {
+    Started,
+    Submitted,
+    /// Клиент отменил заказ или стал недоступен
+    Canceled,
+    /// Заказ отменен в `handle_awaits` из-за молчания клиента
+    Timeout,
+}
+
+impl SaleEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SaleEvent::Started => "started",
+            SaleEvent::Submitted => "submitted",
+            SaleEvent::Canceled => "canceled",
+            SaleEvent::Timeout => "timeout",
+        }
+    }
+}
+
+/// Событие заказа, прочитанное из журнала
+#[derive(Debug, Clone)]
+pub struct SaleRecord {
+    pub time: DateTime<Utc>,
+    pub order_id: String,
+    pub event: SaleEvent,
+    pub paper_type: String,
+    pub paper_size: String,
+    pub photos: usize,
+    pub total: Option<Money>,
+}
+
+/// События заказов для отчетов. Пишутся в журнал переписки рядом со сменой этапов
+#[derive(Debug, Clone)]
+pub struct SalesLog {
+    audit: AuditLog,
+}
+
+impl SalesLog {
+    pub fn new(audit: AuditLog) -> Self {
+        Self { audit }
+    }
+
+    pub fn from_config() -> Self {
+        SalesLog::new(AuditLog::from_config())
+    }
+
+    pub fn append(&self, event: SaleEvent, order: &Order) {
+        self.audit.append(
+            &order.chat_id,
+            AuditEntry::Sale {
+                order_id: order.trace_id.clone(),
+                event,
+                paper_type: order.paper_type().to_string(),
+                paper_size: order.paper_size().to_string(),
+                photos: order.files_count(),
+                total: order.total(),
+            },
+        );
+    }
+
+    /// События с `from` по `to` включительно, дни считаются по времени магазина
+    pub fn read(&self, from: NaiveDate, to: NaiveDate, tz: Tz) -> Result<Vec<SaleRecord>> {
+        let records = self
+            .audit
+            .records()?
+            .into_iter()
+            .filter_map(|record| match record.entry {
+                AuditEntry::Sale {
+                    order_id,
+                    event,
+                    paper_type,
+                    paper_size,
+                    photos,
+                    total,
+                } => Some(SaleRecord {
+                    time: record.time,
+                    order_id,
+                    event,
+                    paper_type,
+                    paper_size,
+                    photos,
+                    total,
+                }),
+                _ => None,
+            })
+            .filter(|record| {
+                let date = record.time.with_timezone(&tz).date_naive();
+                from <= date && date <= to
+            })
+            .collect();
+        Ok(records)
+    }
+
+    /// События за период в CSV, даты в формате ГГГГ-ММ-ДД, `to` по умолчанию равен `from`
+    pub fn export(&self, from: &str, to: Option<&str>) -> Result<String> {
+        let parse = |date: &str| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| Error::ReportInvalid(format!("date {}", date)))
+        };
+        let from = parse(from)?;
+        let to = to.map(parse).transpose()?.unwrap_or(from);
+        let tz = Schedule::new().timezone();
+        Ok(to_csv(&self.read(from, to, tz)?, tz))
+    }
+}
+
+/// Выручка по бумаге и размеру
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Revenue {
+    pub orders: usize,
+    pub photos: usize,
+    pub total: Option<Money>,
+}
+
+/// Итоги дня
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DailyReport {
+    pub date: NaiveDate,
+    pub started: usize,
+    pub submitted: usize,
+    pub canceled: usize,
+    pub timeouts: usize,
+    pub photos: usize,
+    pub revenue: Option<Money>,
+    pub by_paper: BTreeMap<(String, String), Revenue>,
+}
+
+impl DailyReport {
+    pub fn new(date: NaiveDate, records: &[SaleRecord]) -> Self {
+        let mut report = DailyReport {
+            date,
+            started: 0,
+            submitted: 0,
+            canceled: 0,
+            timeouts: 0,
+            photos: 0,
+            revenue: None,
+            by_paper: BTreeMap::new(),
+        };
+        for record in records {
+            match record.event {
+                SaleEvent::Started => report.started += 1,
+                SaleEvent::Canceled => report.canceled += 1,
+                SaleEvent::Timeout => report.timeouts += 1,
+                SaleEvent::Submitted => {
+                    report.submitted += 1;
+                    report.photos += record.photos;
+                    report.revenue = add(report.revenue, record.total);
+                    let key = (record.paper_type.clone(), record.paper_size.clone());
+                    let revenue = report.by_paper.entry(key).or_insert(Revenue {
+                        orders: 0,
+                        photos: 0,
+                        total: None,
+                    });
+                    revenue.orders += 1;
+                    revenue.photos += record.photos;
+                    revenue.total = add(revenue.total, record.total);
+                }
+            }
+        }
+        report
+    }
+
+    /// Доля переданных в печать от начатых за день, в процентах
+    pub fn conversion(&self) -> Option<usize> {
+        (self.started > 0).then(|| self.submitted * 100 / self.started)
+    }
+
+    /// Сообщение администратору
+    pub fn summary(&self) -> String {
+        let mut output = format!("Отчет за {}\n", self.date.format("%d.%m.%Y"));
+        let _ = writeln!(output, "Начато заказов: {}", self.started);
+        let _ = match self.conversion() {
+            Some(conversion) => writeln!(
+                output,
+                "Передано в печать: {} (конверсия {}%)",
+                self.submitted, conversion
+            ),
+            None => writeln!(output, "Передано в печать: {}", self.submitted),
+        };
+        let _ = writeln!(output, "Фото: {}", self.photos);
+        let _ = writeln!(output, "Выручка: {}", format_total(self.revenue));
+        for ((paper, size), revenue) in &self.by_paper {
+            let _ = writeln!(
+                output,
+                "- {} {}: заказов {}, фото {}, {}",
+                paper,
+                size,
+                revenue.orders,
+                revenue.photos,
+                format_total(revenue.total)
+            );
+        }
+        let _ = writeln!(output, "Отменено клиентом: {}", self.canceled);
+        let _ = write!(output, "Отменено по таймауту: {}", self.timeouts);
+        output
+    }
+}
+
+fn add(sum: Option<Money>, total: Option<Money>) -> Option<Money> {
+    match (sum, total) {
+        (Some(sum), Some(total)) => match sum.checked_add(total) {
+            Ok(sum) => Some(sum),
+            // Валюта каталога сменилась в течение дня
+            Err(e) => {
+                warn!("[sales] {} skipped: {}", total, e);
+                Some(sum)
+            }
+        },
+        (sum, total) => sum.or(total),
+    }
+}
+
+fn format_total(total: Option<Money>) -> String {
+    total.map(|t| t.to_string()).unwrap_or("0".to_string())
+}
+
+/// События заказов в CSV для выгрузки в таблицу
+pub fn to_csv(records: &[SaleRecord], tz: Tz) -> String {
+    let mut output = "time,order_id,event,paper_type,paper_size,photos,total,currency\n".to_owned();
+    for record in records {
+        let (total, currency) = match record.total {
+            Some(total) => (
+                decimal(total),
+                format!("{:?}", total.currency).to_uppercase(),
+            ),
+            None => (String::new(), String::new()),
+        };
+        let _ = writeln!(
+            output,
+            "{},{},{},{},{},{},{},{}",
+            record.time.with_timezone(&tz).format("%Y-%m-%d %H:%M:%S"),
+            csv_field(&record.order_id),
+            record.event.name(),
+            csv_field(&record.paper_type),
+            csv_field(&record.paper_size),
+            record.photos,
+            total,
+            currency
+        );
+    }
+    output
+}
+
+/// CSV частями по строкам для сообщений не длиннее `limit` символов,
+/// каждая часть начинается с заголовка
+pub fn split_csv(csv: &str, limit: usize) -> Vec<String> {
+    let mut lines = csv.lines();
+    let header = lines.next().unwrap_or_default();
+    let mut parts = vec![];
+    let mut part = String::new();
+    for line in lines {
+        let len = part.chars().count() + line.chars().count() + 1;
+        if !part.is_empty() && len > limit {
+            parts.push(std::mem::take(&mut part));
+        }
+        if part.is_empty() {
+            part = format!("{}\n", header);
+        }
+        part.push_str(line);
+        part.push('\n');
+    }
+    if !part.is_empty() {
+        parts.push(part);
+    }
+    parts
+}
+
+/// Сумма с точкой и без разделителей разрядов, чтобы таблица приняла ее за число
+fn decimal(money: Money) -> String {
+    let digits = money.currency.minor_digits() as usize;
+    let factor = 10_i64.pow(digits as u32);
+    let sign = if money.amount < 0 { "-" } else { "" };
+    let abs = money.amount.abs();
+    format!("{}{}.{:0digits$}", sign, abs / factor, abs % factor)
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Отправляет итоги дня администратору в REPORT_TIME по времени магазина
+pub struct DailyReporter<'a, T: Transport> {
+    transport: &'a T,
+    sales: SalesLog,
+    tz: Tz,
+    at: Option<NaiveTime>,
+}
+
+impl<'a, T: Transport> DailyReporter<'a, T> {
+    pub fn from_config(transport: &'a T, sales: SalesLog) -> Result<Self> {
+        let at = match config().REPORT_TIME.trim() {
+            "" => None,
+            time => Some(
+                NaiveTime::parse_from_str(time, "%H:%M")
+                    .map_err(|_| Error::ReportInvalid(time.to_string()))?,
+            ),
+        };
+        Ok(Self {
+            transport,
+            sales,
+            tz: Schedule::new().timezone(),
+            at,
+        })
+    }
+
+    pub async fn run(&self) {
+        let Some(at) = self.at else {
+            // Без REPORT_TIME отчет только по запросу
+            return std::future::pending().await;
+        };
+        loop {
+            let now = Utc::now();
+            let next = next_run(now, self.tz, at);
+            tokio::time::sleep((next - now).to_std().unwrap_or_default()).await;
+            let date = next.with_timezone(&self.tz).date_naive();
+            if let Err(e) = self.send(date).await {
+                error!("[daily_report] {}", e);
+            }
+        }
+    }
+
+    async fn send(&self, date: NaiveDate) -> Result<()> {
+        let records = self.sales.read(date, date, self.tz)?;
+        let report = DailyReport::new(date, &records);
+        info!("Daily report for {}", date);
+        self.transport
+            .send_message(config().ADMIN_CHAT_ID.clone(), report.summary())
+            .await
+    }
+}
+
+/// Ближайшее время `at` по часам магазина позже `now`
+pub fn next_run(now: DateTime<Utc>, tz: Tz, at: NaiveTime) -> DateTime<Utc> {
+    let today = now.with_timezone(&tz).date_naive();
+    for date in [today, today + Days::new(1), today + Days::new(2)] {
+        // При переводе часов время может не существовать, тогда берется следующий день
+        if let Some(run) = tz.from_local_datetime(&date.and_time(at)).earliest()
+            && run > now
+        {
+            return run.to_utc();
+        }
+    }
+    now + Days::new(1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stuff::money::Currency;
+
+    fn record(time: &str, event: SaleEvent, paper: &str, photos: usize, total: i64) -> SaleRecord {
+        SaleRecord {
+            time: DateTime::parse_from_rfc3339(time).unwrap().to_utc(),
+            order_id: "o1".to_string(),
+            event,
+            paper_type: paper.to_string(),
+            paper_size: "10x15".to_string(),
+            photos,
+            total: Some(Money::new(total, Currency::Rub)),
+        }
+    }
+
+    #[test]
+    fn daily_report_test() {
+        let records = [
+            record("2026-10-19T01:00:00Z", SaleEvent::Started, "", 0, 0),
+            record("2026-10-19T01:00:00Z", SaleEvent::Started, "", 0, 0),
+            record("2026-10-19T01:00:00Z", SaleEvent::Started, "", 0, 0),
+            record(
+                "2026-10-19T02:00:00Z",
+                SaleEvent::Submitted,
+                "Матовая",
+                10,
+                25000,
+            ),
+            record(
+                "2026-10-19T03:00:00Z",
+                SaleEvent::Submitted,
+                "Матовая",
+                2,
+                5000,
+            ),
+            record("2026-10-19T04:00:00Z", SaleEvent::Timeout, "", 0, 0),
+        ];
+        let date = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+        let report = DailyReport::new(date, &records);
+        assert_eq!(report.conversion(), Some(66));
+        assert_eq!(report.photos, 12);
+        assert_eq!(report.revenue, Some(Money::new(30000, Currency::Rub)));
+        let summary = report.summary();
+        assert!(summary.starts_with("Отчет за 19.10.2026\n"));
+        assert!(summary.contains("Передано в печать: 2 (конверсия 66%)\n"));
+        assert!(summary.contains("- Матовая 10x15: заказов 2, фото 12, 300 руб.\n"));
+        assert!(summary.ends_with("Отменено по таймауту: 1"));
+
+        let csv = to_csv(&records[3..4], Tz::Asia__Vladivostok);
+        assert_eq!(
+            csv,
+            "time,order_id,event,paper_type,paper_size,photos,total,currency\n\
+             2026-10-19 12:00:00,o1,submitted,Матовая,10x15,10,250.00,RUB\n"
+        );
+        assert_eq!(csv_field("Глянец, 260 г"), "\"Глянец, 260 г\"");
+
+        let csv = to_csv(&records, Tz::Asia__Vladivostok);
+        let parts = split_csv(&csv, 130);
+        assert_eq!(parts.len(), records.len());
+        for part in &parts {
+            assert!(part.starts_with("time,order_id,"));
+            assert!(part.chars().count() <= 130);
+            assert_eq!(part.lines().count(), 2);
+        }
+        assert_eq!(split_csv(&csv, 4000), vec![csv]);
+    }
+
+    #[test]
+    fn next_run_test() {
+        let at = NaiveTime::from_hms_opt(21, 0, 0).unwrap();
+        let tz = Tz::Asia__Vladivostok;
+        let now = DateTime::parse_from_rfc3339("2026-10-19T10:00:00Z")
+            .unwrap()
+            .to_utc();
+        assert_eq!(
+            next_run(now, tz, at).to_rfc3339(),
+            "2026-10-19T11:00:00+00:00"
+        );
+        let now = DateTime::parse_from_rfc3339("2026-10-19T11:00:00Z")
+            .unwrap()
+            .to_utc();
+        assert_eq!(
+            next_run(now, tz, at).to_rfc3339(),
+            "2026-10-20T11:00:00+00:00"
+        );
+    }
+}
@@ -1,17 +1,17 @@
 use std::collections::HashMap;
-use crate::stuff::data_types::OrderState;
+use crate::stuff::data_types::Order;
 use crate::stuff::error::{Error, Result};
 
 pub trait Repository {
-    fn get_order(&self, chat_id: &str) -> Option<OrderState>;
-    fn get_orders(&self) -> HashMap<String, OrderState>;
-    fn set_order(&mut self, state: OrderState);
+    fn get_order(&self, chat_id: &str) -> Option<Order>;
+    fn get_orders(&self) -> HashMap<String, Order>;
+    fn set_order(&mut self, state: Order);
     fn delete_order(&mut self, chat_id: &str) -> Result<()>;
 }
 
 #[derive(Debug)]
 pub struct OrderRepository {
-    orders: HashMap<String, OrderState>,
+    orders: HashMap<String, Order>,
 }
 
 impl OrderRepository {
@@ -23,22 +23,22 @@ impl OrderRepository {
 }
 
 impl Repository for OrderRepository {
-    fn get_order(&self, chat_id: &str) -> Option<OrderState> {
+    fn get_order(&self, chat_id: &str) -> Option<Order> {
         self.orders.get(chat_id).cloned()
     }
 
-    fn get_orders(&self) -> HashMap<String, OrderState> {
+    fn get_orders(&self) -> HashMap<String, Order> {
         self.orders.clone()
     }
 
-    fn set_order(&mut self, state: OrderState) {
-        let order = self.orders.get_mut(&state.get_chat_id());
+    fn set_order(&mut self, state: Order) {
+        let order = self.orders.get_mut(&state.chat_id);
         match order {
             Some(order) => {
                 *order = state;
             }
             None => {
-                self.orders.insert(state.get_chat_id(), state);
+                self.orders.insert(state.chat_id.clone(), state);
             }
         }
     }
@@ -54,29 +54,18 @@ impl Repository for OrderRepository {
 
 #[cfg(test)]
 mod tests {
-    use std::time::SystemTime;
     use super::*;
+    use crate::stuff::data_types::OrderEvent;
     #[test]
     fn repo_update_order() {
         let mut repo = OrderRepository::new();
-        let order = OrderState::RaperRequested {
-            chat_id: "79146795551".to_string(),
-            customer_name: "John".to_string(),
-            files: vec![],
-            repeats: 0,
-            last_msg_time: SystemTime::now(),
-        };
+        let order = Order::new("79146795551".to_string(), "John".to_string(), vec![]);
         repo.set_order(order);
         println!("Order update result: {:?}", repo);
 
-        let order = OrderState::SizeRequested {
-            chat_id: "79146795552".to_string(),
-            customer_name: "Jane".to_string(),
-            paper: "paper".to_string(),
-            files: vec![],
-            repeats: 0,
-            last_msg_time: SystemTime::now(),
-        };
+        let order = Order::new("79146795552".to_string(), "Jane".to_string(), vec![])
+            .apply(OrderEvent::ProductChosen("product".to_string()))
+            .unwrap();
         repo.set_order(order.clone());
 
         println!("Order update result: {:?}", repo);
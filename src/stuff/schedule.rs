@@ -0,0 +1,274 @@
+use crate::config::config;
+use crate::stuff::error::{Error, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+
+const MINUTES_IN_DAY: u32 = 24 * 60;
+/// Сколько дней вперед искать рабочее время
+const LOOKAHEAD_DAYS: i64 = 366;
+const WEEKDAYS: [&str; 7] = ["пн", "вт", "ср", "чт", "пт", "сб", "вс"];
+
+/// Часы работы одного дня в минутах от полуночи, конец не включается
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct Hours {
+    open: u32,
+    close: u32,
+}
+
+/// Режим работы точки: часы по дням недели, праздничные дни и часовой пояс
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    tz: Tz,
+    /// Индекс - день недели начиная с понедельника, `None` - выходной
+    week: [Option<Hours>; 7],
+    holidays: Vec<NaiveDate>,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Schedule::parse(
+            &config().SHOP_TIMEZONE,
+            &config().SHOP_HOURS,
+            &config().SHOP_HOLIDAYS,
+        )
+        .expect("shop schedule invalid")
+    }
+
+    /// Часы работы: "09:00-20:00" каждый день
+    /// или "mon-fri 09:00-20:00, sat 10:00-16:00" - не указанные дни выходные.
+    /// Праздники через запятую: "2026-01-01,2026-01-02".
+    pub fn parse(tz: &str, hours: &str, holidays: &str) -> Result<Schedule> {
+        let tz = tz
+            .parse::<Tz>()
+            .map_err(|_| Error::ScheduleInvalid(format!("timezone {}", tz)))?;
+        let mut week = [None; 7];
+        for part in hours.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (days, time) = match part.split_once(' ') {
+                Some((days, time)) => (parse_days(days)?, time.trim()),
+                None => ((0..7).collect(), part),
+            };
+            let time = parse_hours(time)?;
+            for day in days {
+                week[day] = Some(time);
+            }
+        }
+        let holidays = holidays
+            .split(',')
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+            .map(|d| {
+                NaiveDate::parse_from_str(d, "%Y-%m-%d")
+                    .map_err(|_| Error::ScheduleInvalid(format!("holiday {}", d)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Schedule { tz, week, holidays })
+    }
+
+    /// Время по часовому поясу точки
+    pub fn timezone(&self) -> Tz {
+        self.tz
+    }
+
+    /// Часы работы для клиента: "пн-пт 09:00-20:00, сб 10:00-16:00, вс выходной"
+    pub fn hours_text(&self) -> String {
+        if self.week.iter().all(|h| *h == self.week[0]) {
+            return match self.week[0] {
+                Some(Hours { open: 0, close }) if close == MINUTES_IN_DAY => {
+                    "круглосуточно".to_owned()
+                }
+                Some(h) => format!("ежедневно {}", format_hours(h)),
+                None => "выходной".to_owned(),
+            };
+        }
+        let mut parts = vec![];
+        let mut from = 0;
+        for day in 1..=7 {
+            if day < 7 && self.week[day] == self.week[from] {
+                continue;
+            }
+            let days = match day - 1 == from {
+                true => WEEKDAYS[from].to_owned(),
+                false => format!("{}-{}", WEEKDAYS[from], WEEKDAYS[day - 1]),
+            };
+            let hours = self.week[from].map_or("выходной".to_owned(), format_hours);
+            parts.push(format!("{} {}", days, hours));
+            from = day;
+        }
+        parts.join(", ")
+    }
+
+    pub fn local(&self, time: DateTime<Utc>) -> DateTime<Tz> {
+        time.with_timezone(&self.tz)
+    }
+
+    pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+        let local = now.with_timezone(&self.tz);
+        self.intervals(local.date_naive())
+            .any(|(open, close)| open <= now && now < close)
+    }
+
+    /// Ближайшее время открытия, `now` - если точка работает
+    pub fn next_opening(&self, now: DateTime<Utc>) -> Option<DateTime<Tz>> {
+        self.open_intervals(now)
+            .next()
+            .map(|(open, _)| open.with_timezone(&self.tz))
+    }
+
+    /// Момент, когда пройдет `work` рабочего времени начиная с `now`
+    pub fn add_working_time(&self, now: DateTime<Utc>, work: Duration) -> Option<DateTime<Tz>> {
+        let mut remaining = work;
+        for (open, close) in self.open_intervals(now) {
+            let available = close - open;
+            if remaining <= available {
+                return Some((open + remaining).with_timezone(&self.tz));
+            }
+            remaining -= available;
+        }
+        None
+    }
+
+    /// Рабочие интервалы начиная с `now`, первый интервал может быть неполным
+    fn open_intervals(
+        &self,
+        now: DateTime<Utc>,
+    ) -> impl Iterator<Item = (DateTime<Utc>, DateTime<Utc>)> + '_ {
+        let today = now.with_timezone(&self.tz).date_naive();
+        (0..LOOKAHEAD_DAYS)
+            .filter_map(move |offset| today.checked_add_signed(Duration::days(offset)))
+            .flat_map(move |date| self.intervals(date))
+            .filter(move |(_, close)| *close > now)
+            .map(move |(open, close)| (open.max(now), close))
+    }
+
+    fn intervals(&self, date: NaiveDate) -> impl Iterator<Item = (DateTime<Utc>, DateTime<Utc>)> {
+        let hours = match self.holidays.contains(&date) {
+            true => None,
+            false => self.week[date.weekday().num_days_from_monday() as usize],
+        };
+        hours
+            .and_then(|h| {
+                let open = self.at(date, h.open)?;
+                let close = self.at(date, h.close)?;
+                Some((open, close))
+            })
+            .into_iter()
+    }
+
+    fn at(&self, date: NaiveDate, minutes: u32) -> Option<DateTime<Utc>> {
+        let local: NaiveDateTime = date.and_hms_opt(0, 0, 0)? + Duration::minutes(minutes as i64);
+        self.tz
+            .from_local_datetime(&local)
+            .earliest()
+            .map(|t| t.with_timezone(&Utc))
+    }
+}
+
+fn parse_days(days: &str) -> Result<Vec<usize>> {
+    let day = |d: &str| {
+        d.parse::<Weekday>()
+            .map(|w| w.num_days_from_monday() as usize)
+            .map_err(|_| Error::ScheduleInvalid(format!("weekday {}", d)))
+    };
+    match days.split_once('-') {
+        Some((from, to)) => {
+            let (from, to) = (day(from)?, day(to)?);
+            Ok((0..7)
+                .map(|i| (from + i) % 7)
+                .take((to + 7 - from) % 7 + 1)
+                .collect())
+        }
+        None => Ok(vec![day(days)?]),
+    }
+}
+
+fn format_hours(hours: Hours) -> String {
+    let time = |m: u32| format!("{:02}:{:02}", m / 60, m % 60);
+    format!("{}-{}", time(hours.open), time(hours.close))
+}
+
+fn parse_hours(time: &str) -> Result<Hours> {
+    let invalid = || Error::ScheduleInvalid(format!("hours {}", time));
+    let minutes = |t: &str| -> Option<u32> {
+        let (h, m) = t.trim().split_once(':')?;
+        let (h, m) = (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?);
+        let total = h * 60 + m;
+        (m < 60 && total <= MINUTES_IN_DAY).then_some(total)
+    };
+    let (open, close) = time.split_once('-').ok_or_else(invalid)?;
+    let (open, close) = (
+        minutes(open).ok_or_else(invalid)?,
+        minutes(close).ok_or_else(invalid)?,
+    );
+    if open >= close {
+        return Err(invalid());
+    }
+    Ok(Hours { open, close })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn schedule() -> Schedule {
+        Schedule::parse(
+            "Asia/Vladivostok",
+            "mon-fri 09:00-20:00, sat 10:00-16:00",
+            "2026-10-21",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parse_test() {
+        assert!(Schedule::parse("UTC", "00:00-24:00", "").is_ok());
+        assert!(Schedule::parse("Mars/Base", "09:00-20:00", "").is_err());
+        assert!(Schedule::parse("UTC", "20:00-09:00", "").is_err());
+        assert!(Schedule::parse("UTC", "fri-mon 09:00-20:00", "").is_ok());
+        assert!(Schedule::parse("UTC", "09:00-20:00", "21.10.2026").is_err());
+    }
+
+    #[test]
+    fn hours_text_test() {
+        assert_eq!(
+            schedule().hours_text(),
+            "пн-пт 09:00-20:00, сб 10:00-16:00, вс выходной"
+        );
+        let daily = Schedule::parse("UTC", "09:00-20:00", "").unwrap();
+        assert_eq!(daily.hours_text(), "ежедневно 09:00-20:00");
+        let always = Schedule::parse("UTC", "00:00-24:00", "").unwrap();
+        assert_eq!(always.hours_text(), "круглосуточно");
+    }
+
+    #[test]
+    fn open_hours_test() {
+        let schedule = schedule();
+        // Понедельник 19.10.2026 12:00 по Владивостоку
+        assert!(schedule.is_open(utc("2026-10-19T12:00:00+10:00")));
+        assert!(!schedule.is_open(utc("2026-10-19T03:00:00+10:00")));
+        // Среда - праздник, открываемся в четверг
+        let opening = schedule.next_opening(utc("2026-10-20T21:00:00+10:00"));
+        assert_eq!(
+            opening,
+            Some(utc("2026-10-22T09:00:00+10:00").with_timezone(&schedule.tz))
+        );
+    }
+
+    #[test]
+    fn add_working_time_test() {
+        let schedule = schedule();
+        let ready = schedule.add_working_time(utc("2026-10-19T19:00:00+10:00"), Duration::hours(2));
+        assert_eq!(
+            ready,
+            Some(utc("2026-10-20T10:00:00+10:00").with_timezone(&schedule.tz))
+        );
+        let ready = schedule.add_working_time(utc("2026-10-24T03:00:00+10:00"), Duration::hours(1));
+        assert_eq!(
+            ready,
+            Some(utc("2026-10-24T11:00:00+10:00").with_timezone(&schedule.tz))
+        );
+    }
+}
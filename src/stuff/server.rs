@@ -0,0 +1,173 @@
+use crate::config::config;
+use crate::stuff::files::{FileStore, image_type};
+use crate::stuff::metrics::metrics;
+use crate::stuff::payment::PaymentEvent;
+use crate::stuff::worker_api::WorkerEvent;
+use crate::stuff::worker_auth::WorkerAuth;
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::Utc;
+use log::{error, info, warn};
+use tokio::sync::mpsc::UnboundedSender;
+
+#[derive(Clone)]
+struct AppState {
+    payment_events: UnboundedSender<PaymentEvent>,
+    worker_events: UnboundedSender<WorkerEvent>,
+    worker_auth: WorkerAuth,
+    files: Option<FileStore>,
+}
+
+/// `files` - прокси файлов Telegram для FILES_PUBLIC_URL, без него /files не обслуживается
+pub async fn serve(
+    addr: String,
+    payment_events: UnboundedSender<PaymentEvent>,
+    worker_events: UnboundedSender<WorkerEvent>,
+    worker_auth: WorkerAuth,
+    files: Option<FileStore>,
+) {
+    let app = router(payment_events, worker_events, worker_auth, files);
+    let listener = match tokio::net::TcpListener::bind(&addr).await {
+        Ok(l) => l,
+        Err(e) => {
+            error!("[serve] failed to bind {}: {}", addr, e);
+            return;
+        }
+    };
+    info!("HTTP server listening on {}", addr);
+    if let Err(e) = axum::serve(listener, app).await {
+        error!("[serve] {}", e);
+    }
+}
+
+fn router(
+    payment_events: UnboundedSender<PaymentEvent>,
+    worker_events: UnboundedSender<WorkerEvent>,
+    worker_auth: WorkerAuth,
+    files: Option<FileStore>,
+) -> Router {
+    let mut router = Router::new()
+        .route("/payment/callback", post(payment_callback))
+        .route("/worker/callback", post(worker_callback));
+    if config().METRICS_ENABLED {
+        router = router.route("/metrics", get(metrics_handler));
+    }
+    if files.is_some() {
+        router = router.route("/files/{reference}", get(file_proxy));
+    }
+    router.with_state(AppState {
+        payment_events,
+        worker_events,
+        worker_auth,
+        files,
+    })
+}
+
+async fn payment_callback(
+    State(state): State<AppState>,
+    Json(event): Json<PaymentEvent>,
+) -> StatusCode {
+    info!("Payment callback for {}", event.payment_id);
+    match state.payment_events.send(event) {
+        Ok(_) => StatusCode::OK,
+        Err(_) => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+async fn metrics_handler() -> impl IntoResponse {
+    (
+        [(
+            CONTENT_TYPE,
+            "application/openmetrics-text; version=1.0.0; charset=utf-8",
+        )],
+        metrics().encode(),
+    )
+}
+
+/// Файл Telegram по "канал:file_id" или "file_id". Бот скачивает его сам,
+/// ссылка с токеном не покидает процесс
+async fn file_proxy(State(state): State<AppState>, Path(reference): Path<String>) -> Response {
+    let Some(files) = &state.files else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match files.telegram_file(&reference).await {
+        Ok(bytes) => {
+            let content_type =
+                image_type(&bytes).map_or("application/octet-stream", |(mime, _)| mime);
+            ([(CONTENT_TYPE, content_type)], bytes).into_response()
+        }
+        Err(e) => {
+            warn!("File {} not served: {}", reference, e);
+            StatusCode::NOT_FOUND.into_response()
+        }
+    }
+}
+
+/// Подпись проверяется по сырому телу, до разбора JSON
+async fn worker_callback(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    if let Err(e) = state.worker_auth.verify(&headers, &body, Utc::now()) {
+        warn!("Worker callback rejected: {}", e);
+        return StatusCode::UNAUTHORIZED;
+    }
+    let event = match serde_json::from_slice::<WorkerEvent>(&body) {
+        Ok(event) => event,
+        Err(e) => {
+            warn!("Worker callback malformed: {}", e);
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+    info!("Worker callback for {}: {:?}", event.order_id, event.status);
+    match state.worker_events.send(event) {
+        Ok(_) => StatusCode::OK,
+        Err(_) => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stuff::worker_api::WorkerStatus;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn worker_callback_test() {
+        let auth = WorkerAuth::Hmac("secret".to_string());
+        let (payment_tx, _payment_rx) = mpsc::unbounded_channel();
+        let (worker_tx, mut worker_rx) = mpsc::unbounded_channel();
+        let app = router(payment_tx, worker_tx, auth.clone(), None);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/worker/callback", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = reqwest::Client::new();
+        let body = r#"{"order_id": "A-15", "chat_id": "79146795555@c.us", "status": "ready"}"#;
+        let unsigned = client.post(&url).body(body).send().await.unwrap();
+        assert_eq!(unsigned.status(), StatusCode::UNAUTHORIZED);
+        assert!(worker_rx.try_recv().is_err());
+
+        let mut signed = client.post(&url).body(body);
+        for (name, value) in auth.headers(body.as_bytes(), Utc::now()) {
+            signed = signed.header(name, value);
+        }
+        assert_eq!(signed.send().await.unwrap().status(), StatusCode::OK);
+        let event = worker_rx.try_recv().unwrap();
+        assert_eq!(event.order_id, "A-15");
+        assert_eq!(event.status, WorkerStatus::Ready);
+
+        // METRICS_ENABLED выключен в тестовых настройках
+        let metrics = client.get(url.replace("/worker/callback", "/metrics"));
+        assert_eq!(
+            metrics.send().await.unwrap().status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+}
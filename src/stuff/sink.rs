@@ -0,0 +1,259 @@
+use crate::config::config;
+use crate::stuff::data_types::{Order, OrderMessage};
+use crate::stuff::email::{Attachment, Mailer, mailbox};
+use crate::stuff::error::{Error, Result};
+use crate::stuff::files::{FileStore, image_type, public_url};
+use crate::stuff::hot_folder::HotFolder;
+use crate::stuff::worker::Worker;
+use chrono::{DateTime, Utc};
+use lettre::message::Mailbox;
+
+/// Получатель готовых заказов - сервис или станция печати
+pub trait OrderSink {
+    /// Передает заказ в печать
+    async fn submit(&self, order: Order) -> Result<Submitted>;
+}
+
+/// Заказ принят в печать
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Submitted {
+    /// Номер заказа для клиента
+    pub order_id: String,
+    /// Время готовности, если его рассчитал получатель
+    pub ready_at: Option<DateTime<Utc>>,
+}
+
+impl Submitted {
+    pub fn local(order_id: String) -> Self {
+        Self {
+            order_id,
+            ready_at: None,
+        }
+    }
+}
+
+/// Получатель заказов, выбранный в ORDER_SINK
+pub enum Sink {
+    Worker(Worker),
+    HotFolder(HotFolder),
+    Email(Box<EmailSink>),
+}
+
+impl Sink {
+    pub fn from_config(client: reqwest::Client, files: FileStore) -> Result<Sink> {
+        match config().ORDER_SINK.as_str() {
+            "worker" => Ok(Sink::Worker(Worker::new(client)?)),
+            "folder" => Ok(Sink::HotFolder(HotFolder::new(files)?)),
+            "email" => Ok(Sink::Email(Box::new(EmailSink::from_config(files)?))),
+            other => Err(Error::OrderFailed(format!("unknown ORDER_SINK {}", other))),
+        }
+    }
+}
+
+impl OrderSink for Sink {
+    async fn submit(&self, order: Order) -> Result<Submitted> {
+        match self {
+            Sink::Worker(sink) => sink.submit(order).await,
+            Sink::HotFolder(sink) => sink.submit(order).await,
+            Sink::Email(sink) => sink.submit(order).await,
+        }
+    }
+}
+
+/// Вложения одного письма: с base64 письмо остается в распространенном лимите SMTP 10 МБ
+const MAX_EMAIL_ATTACHMENTS_SIZE: usize = 7 * 1024 * 1024;
+
+/// Заказ письмом с фото во вложении, для точек без станции печати.
+/// Фото больше MAX_EMAIL_ATTACHMENTS_SIZE делятся на несколько писем,
+/// файл больше лимита передается ссылкой.
+/// Если файл не скачался или письмо не ушло, заказ остается в очереди повторной отправки,
+/// окончательный отказ SMTP (5xx, например из-за размера) не повторяется
+pub struct EmailSink {
+    mailer: Mailer,
+    to: Mailbox,
+    files: FileStore,
+}
+
+impl EmailSink {
+    pub fn from_config(files: FileStore) -> Result<EmailSink> {
+        Ok(EmailSink {
+            mailer: Mailer::from_config()?,
+            to: mailbox(&config().ORDER_EMAIL_TO)?,
+            files,
+        })
+    }
+
+    /// Фото заказа по порядку: "001.jpg", "002.png", со ссылкой на исходный файл
+    async fn attachments(&self, message: &OrderMessage) -> Result<Vec<(String, Attachment)>> {
+        let mut attachments = vec![];
+        for (idx, url) in message.files.iter().enumerate() {
+            let bytes = self.files.download(url).await?;
+            let (content_type, ext) = image_type(&bytes).unwrap_or(("image/jpeg", "jpg"));
+            let attachment = Attachment {
+                name: format!("{:03}.{}", idx + 1, ext),
+                content_type,
+                bytes,
+            };
+            attachments.push((url.clone(), attachment));
+        }
+        Ok(attachments)
+    }
+}
+
+/// Вложения, разложенные по письмам
+#[derive(Default)]
+struct EmailParts {
+    parts: Vec<Vec<Attachment>>,
+    /// Файлы больше лимита одного письма
+    links: Vec<String>,
+}
+
+fn split_attachments(attachments: Vec<(String, Attachment)>, limit: usize) -> EmailParts {
+    let mut split = EmailParts::default();
+    let mut size = 0;
+    for (url, attachment) in attachments {
+        let len = attachment.bytes.len();
+        if len > limit {
+            split
+                .links
+                .push(format!("{}: {}", attachment.name, public_url(&url)));
+            continue;
+        }
+        match split.parts.last_mut() {
+            Some(part) if size + len <= limit => part.push(attachment),
+            _ => {
+                size = 0;
+                split.parts.push(vec![attachment]);
+            }
+        }
+        size += len;
+    }
+    split
+}
+
+impl OrderSink for EmailSink {
+    async fn submit(&self, order: Order) -> Result<Submitted> {
+        let id = local_order_id(&order, Utc::now());
+        let message = OrderMessage::try_from(order)?;
+        let EmailParts { mut parts, links } = split_attachments(
+            self.attachments(&message).await?,
+            MAX_EMAIL_ATTACHMENTS_SIZE,
+        );
+        if parts.is_empty() {
+            parts.push(vec![]);
+        }
+        let count = parts.len();
+        let mut text = email_text(&message);
+        if !links.is_empty() {
+            text.push_str("\nФайлы больше лимита письма, по ссылкам:\n");
+            text.push_str(&links.join("\n"));
+        }
+        for (idx, attachments) in parts.into_iter().enumerate() {
+            let (subject, text) = if count == 1 {
+                (format!("Заказ {}", id), text.clone())
+            } else {
+                (
+                    format!("Заказ {}, письмо {} из {}", id, idx + 1, count),
+                    format!("{}\nПисьмо {} из {}", text, idx + 1, count),
+                )
+            };
+            self.mailer
+                .send_with_attachments(&self.to, &subject, &text, attachments)
+                .await?;
+        }
+        Ok(Submitted::local(id))
+    }
+}
+
+/// Описание заказа в письме, сами фото во вложении
+fn email_text(message: &OrderMessage) -> String {
+    let mut lines = vec![
+        format!("Чат: {}", message.chat_id),
+        format!("Телефон: {}", message.phone),
+        format!("Имя: {}", message.name),
+        format!("Товар: {}", message.product),
+    ];
+    lines.extend(
+        message
+            .options
+            .iter()
+            .map(|o| format!("{}: {}", o.name, o.value)),
+    );
+    if let Some(fit) = message.fit {
+        lines.push(format!("Кадрирование: {}", fit));
+    }
+    if message.payment_id.is_some() {
+        lines.push(format!("Оплачен: {}", message.paid));
+    }
+    lines.push(format!("Фото: {}", message.files.len()));
+    lines.push(format!("Сумма: {}", message.total));
+    lines.join("\n")
+}
+
+/// Номер заказа, если его не выдает сервис печати: время и конец номера телефона
+pub fn local_order_id(order: &Order, now: DateTime<Utc>) -> String {
+    let digits: Vec<char> = order.phone().chars().filter(char::is_ascii_digit).collect();
+    let suffix: String = digits[digits.len().saturating_sub(4)..].iter().collect();
+    format!("{}-{}", now.format("%y%m%d-%H%M%S"), suffix)
+}
+
+/// Запоминает принятые заказы, при заданных `rejection` отклоняет их,
+/// при `unavailable` отвечает как недоступный сервис, при `refused` отказывает окончательно
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeSink {
+    pub orders: std::sync::Mutex<Vec<Order>>,
+    pub rejection: std::sync::Mutex<Option<Vec<crate::stuff::worker_api::FieldError>>>,
+    pub unavailable: std::sync::atomic::AtomicBool,
+    pub refused: std::sync::atomic::AtomicBool,
+}
+
+#[cfg(test)]
+impl OrderSink for FakeSink {
+    async fn submit(&self, order: Order) -> Result<Submitted> {
+        if let Some(errors) = self.rejection.lock().unwrap().clone() {
+            return Err(Error::OrderRejected(errors));
+        }
+        if self.unavailable.load(std::sync::atomic::Ordering::Relaxed) {
+            return Err(Error::OrderFailed("unavailable".to_string()));
+        }
+        if self.refused.load(std::sync::atomic::Ordering::Relaxed) {
+            return Err(Error::OrderRefused("403 Forbidden".to_string()));
+        }
+        let mut orders = self.orders.lock().unwrap();
+        orders.push(order);
+        Ok(Submitted::local(orders.len().to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn attachment(name: &str, size: usize) -> (String, Attachment) {
+        let attachment = Attachment {
+            name: name.to_string(),
+            content_type: "image/jpeg",
+            bytes: vec![0; size],
+        };
+        (format!("http://localhost/{}", name), attachment)
+    }
+
+    #[test]
+    fn split_attachments_test() {
+        let attachments = vec![
+            attachment("001.jpg", 6),
+            attachment("002.jpg", 4),
+            attachment("003.jpg", 11),
+            attachment("004.jpg", 3),
+        ];
+        let split = split_attachments(attachments, 10);
+        let names: Vec<Vec<&str>> = split
+            .parts
+            .iter()
+            .map(|part| part.iter().map(|a| a.name.as_str()).collect())
+            .collect();
+        assert_eq!(names, vec![vec!["001.jpg", "002.jpg"], vec!["004.jpg"]]);
+        assert_eq!(split.links, vec!["003.jpg: http://localhost/003.jpg"]);
+    }
+}
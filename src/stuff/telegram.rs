@@ -0,0 +1,374 @@
+use crate::config::config;
+use crate::stuff::data_types::{InstanceState, Message, OrderFile, ReceivedImage, ReceivedMessage};
+use crate::stuff::error::{Error, Result};
+use crate::stuff::image_info::Dimensions;
+use crate::stuff::metrics::metrics;
+use crate::stuff::tg_types::{File, IncomingMessage, Response, SendMessage, Update, User};
+use crate::stuff::transport::Transport;
+use log::{debug, error};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+/// Файл бота в заказе: "telegram:file_id". Ссылка на скачивание содержит
+/// токен и действует около часа, поэтому в заказе хранится только file_id.
+pub const FILE_SCHEME: &str = "telegram:";
+
+/// Транспорт через Telegram Bot API: long polling `getUpdates`,
+/// фото скачиваются через `TelegramFiles`.
+pub struct Telegram {
+    client: reqwest::Client,
+    api_url: String,
+    files: TelegramFiles,
+    timeout_seconds: u16,
+    /// Следующее ожидаемое обновление, предыдущие Telegram считает полученными
+    offset: AtomicI64,
+}
+
+impl Telegram {
+    pub fn new(client: reqwest::Client) -> Self {
+        Telegram::with_api(
+            client,
+            &config().TELEGRAM_API_URL,
+            &config().TELEGRAM_BOT_TOKEN,
+        )
+    }
+
+    pub fn with_api(client: reqwest::Client, api_url: &str, token: &str) -> Self {
+        Self {
+            files: TelegramFiles {
+                client: client.clone(),
+                api_url: format!("{}/bot{}", api_url, token),
+                file_url: format!("{}/file/bot{}", api_url, token),
+            },
+            client,
+            api_url: format!("{}/bot{}", api_url, token),
+            timeout_seconds: 5,
+            offset: AtomicI64::new(0),
+        }
+    }
+
+    pub fn files(&self) -> TelegramFiles {
+        self.files.clone()
+    }
+
+    fn parse_update(&self, message: Option<IncomingMessage>) -> Message {
+        let Some(message) = message else {
+            return Message::Empty;
+        };
+        let chat_id = message.chat.id.to_string();
+        let message_id = Some(message.message_id.to_string());
+        let customer_name = message.from.as_ref().map(full_name).unwrap_or_default();
+        let largest = message
+            .photo
+            .as_ref()
+            .and_then(|sizes| sizes.iter().max_by_key(|s| s.width * s.height));
+        let file = match (largest, &message.document) {
+            (Some(photo), _) => Some(OrderFile {
+                url: file_ref(&photo.file_id),
+                dimensions: Some(Dimensions {
+                    width: photo.width,
+                    height: photo.height,
+                }),
+            }),
+            (None, Some(doc)) if doc.mime_type.as_deref().is_some_and(is_image) => {
+                Some(OrderFile {
+                    url: file_ref(&doc.file_id),
+                    dimensions: None,
+                })
+            }
+            _ => None,
+        };
+        match (file, message.text) {
+            (Some(file), _) => Message::Image(ReceivedImage {
+                chat_id,
+                customer_name,
+                message_id,
+                file,
+            }),
+            (None, Some(text)) => Message::Text(ReceivedMessage {
+                chat_id,
+                customer_name,
+                message_id,
+                message: text,
+            }),
+            (None, None) => Message::Empty,
+        }
+    }
+}
+
+/// Скачивание файлов бота по file_id
+#[derive(Clone)]
+pub struct TelegramFiles {
+    client: reqwest::Client,
+    api_url: String,
+    file_url: String,
+}
+
+impl TelegramFiles {
+    /// Файл по свежей ссылке из `getFile`. Ссылка содержит токен,
+    /// поэтому убирается и из ошибок запроса
+    pub async fn download(&self, file_id: &str) -> Result<Vec<u8>> {
+        let url = self.file_link(file_id).await?;
+        let request = self.client.get(&url);
+        let response = metrics()
+            .timed("telegram", "download", request)
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::from_api_status(
+                status,
+                format!("file {} download failed", file_id),
+            ));
+        }
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        Ok(bytes.to_vec())
+    }
+
+    /// Ссылка на скачивание файла, действует не меньше часа
+    async fn file_link(&self, file_id: &str) -> Result<String> {
+        let url = format!("{}/getFile", self.api_url);
+        let request = self.client.get(&url).query(&[("file_id", file_id)]);
+        let response = metrics()
+            .timed("telegram", "getFile", request)
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        let file = parse_response::<File>(response).await?;
+        match file.file_path {
+            Some(path) => Ok(format!("{}/{}", self.file_url, path)),
+            None => Err(Error::MalformedNotification(format!(
+                "file {} without file_path",
+                file_id
+            ))),
+        }
+    }
+}
+
+fn file_ref(file_id: &str) -> String {
+    format!("{}{}", FILE_SCHEME, file_id)
+}
+
+fn full_name(user: &User) -> String {
+    match &user.last_name {
+        Some(last_name) => format!("{} {}", user.first_name, last_name),
+        None => user.first_name.clone(),
+    }
+}
+
+fn is_image(mime_type: &str) -> bool {
+    mime_type.starts_with("image/")
+}
+
+/// Результат запроса к Bot API или ошибка по коду статуса
+async fn parse_response<T: DeserializeOwned>(response: reqwest::Response) -> Result<T> {
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(telegram_error(status, body));
+    }
+    match serde_json::from_str::<Response<T>>(&body) {
+        Ok(Response {
+            ok: true,
+            result: Some(result),
+            ..
+        }) => Ok(result),
+        Ok(response) => Err(Error::ApiFailed(status, response.description)),
+        Err(_) => Err(Error::ApiFailed(status, body)),
+    }
+}
+
+/// 403 в Telegram означает, что пользователь заблокировал бота, а не ошибку токена,
+/// остальные 400 относятся к самому сообщению
+fn telegram_error(status: StatusCode, body: String) -> Error {
+    match status {
+        StatusCode::FORBIDDEN => Error::ChatUnreachable(body),
+        StatusCode::BAD_REQUEST if body.contains("chat not found") => Error::ChatUnreachable(body),
+        _ => Error::from_api_status(status, body),
+    }
+}
+
+impl Transport for Telegram {
+    async fn receive_message(&self) -> Result<Message> {
+        let url = format!("{}/getUpdates", self.api_url);
+        let offset = self.offset.load(Ordering::Relaxed);
+        let request = self.client.get(&url).query(&[
+            ("offset", offset),
+            ("limit", 1),
+            ("timeout", self.timeout_seconds as i64),
+        ]);
+        let response = metrics().timed("telegram", "getUpdates", request).await?;
+        let Some(update) = parse_response::<Vec<Update>>(response).await?.pop() else {
+            debug!("Новых сообщений нет");
+            return Ok(Message::Empty);
+        };
+        let message = self.parse_update(update.message);
+        self.offset.store(update.update_id + 1, Ordering::Relaxed);
+        Ok(message)
+    }
+
+    async fn send_message(&self, chat_id: String, text: String) -> Result<()> {
+        self.send_message_id(chat_id, text).await.map(|_| ())
+    }
+
+    async fn send_message_id(&self, chat_id: String, text: String) -> Result<Option<String>> {
+        let url = format!("{}/sendMessage", self.api_url);
+        let msg = SendMessage { chat_id, text };
+        let request = self.client.post(&url).json(&msg);
+        let response = metrics().timed("telegram", "sendMessage", request).await?;
+        match parse_response::<serde_json::Value>(response).await {
+            Ok(sent) => Ok(sent["message_id"].as_i64().map(|id| id.to_string())),
+            Err(e) => {
+                error!("[send_message] {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn instance_state(&self) -> Result<InstanceState> {
+        let url = format!("{}/getMe", self.api_url);
+        let response = metrics()
+            .timed("telegram", "getMe", self.client.get(&url))
+            .await?;
+        match parse_response::<serde_json::Value>(response).await {
+            Ok(_) => Ok(InstanceState::Authorized),
+            Err(Error::ApiUnauthorized(_)) => Ok(InstanceState::NotAuthorized),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::extract::{Path, Query, State};
+    use axum::http::StatusCode;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use serde_json::{Value, json};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    type Sent = Arc<Mutex<Vec<Value>>>;
+
+    fn updates() -> Vec<Value> {
+        vec![
+            json!({"update_id": 10, "message": {
+                "message_id": 1,
+                "chat": {"id": 42, "type": "private"},
+                "from": {"id": 42, "is_bot": false, "first_name": "Иван", "last_name": "Петров"},
+                "date": 1760000000,
+                "text": "Привет"
+            }}),
+            json!({"update_id": 11, "message": {
+                "message_id": 2,
+                "chat": {"id": 42, "type": "private"},
+                "date": 1760000000,
+                "photo": [
+                    {"file_id": "small", "file_unique_id": "s", "width": 320, "height": 240},
+                    {"file_id": "big", "file_unique_id": "b", "width": 1280, "height": 960}
+                ]
+            }}),
+        ]
+    }
+
+    async fn get_updates(Query(query): Query<HashMap<String, i64>>) -> Json<Value> {
+        let offset = query.get("offset").copied().unwrap_or_default();
+        let result: Vec<Value> = updates()
+            .into_iter()
+            .filter(|u| u["update_id"].as_i64().unwrap() >= offset)
+            .take(1)
+            .collect();
+        Json(json!({"ok": true, "result": result}))
+    }
+
+    async fn download(Path(name): Path<String>) -> Vec<u8> {
+        name.into_bytes()
+    }
+
+    async fn get_file(Query(query): Query<HashMap<String, String>>) -> Json<Value> {
+        let path = format!("photos/{}.jpg", query["file_id"]);
+        Json(json!({"ok": true, "result": {"file_id": query["file_id"], "file_path": path}}))
+    }
+
+    async fn send_message(
+        State(sent): State<Sent>,
+        Json(body): Json<Value>,
+    ) -> (StatusCode, Json<Value>) {
+        if body["chat_id"] == "13" {
+            let blocked = json!({"ok": false, "error_code": 403, "description": "Forbidden: bot was blocked by the user"});
+            return (StatusCode::FORBIDDEN, Json(blocked));
+        }
+        sent.lock().unwrap().push(body);
+        (
+            StatusCode::OK,
+            Json(json!({"ok": true, "result": {"message_id": 3}})),
+        )
+    }
+
+    /// Заменяет api.telegram.org локальным сервером
+    async fn stand_in(sent: Sent) -> String {
+        let app = Router::new()
+            .route("/bottoken/getUpdates", get(get_updates))
+            .route("/bottoken/getFile", get(get_file))
+            .route("/file/bottoken/photos/{name}", get(download))
+            .route("/bottoken/sendMessage", post(send_message))
+            .route(
+                "/bottoken/getMe",
+                get(|| async { Json(json!({"ok": true, "result": {"id": 1}})) }),
+            )
+            .with_state(sent);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn telegram_test() {
+        let sent = Sent::default();
+        let api = stand_in(sent.clone()).await;
+        let telegram = Telegram::with_api(reqwest::Client::new(), &api, "token");
+
+        let text = telegram.receive_message().await.unwrap();
+        assert!(matches!(text, Message::Text(m)
+            if m.chat_id == "42" && m.customer_name == "Иван Петров" && m.message == "Привет"));
+        let image = telegram.receive_message().await.unwrap();
+        let Message::Image(image) = image else {
+            panic!("image expected");
+        };
+        assert_eq!(image.file.url, "telegram:big");
+        assert_eq!(telegram.files().download("big").await.unwrap(), b"big.jpg");
+        assert_eq!(
+            image.file.dimensions,
+            Some(Dimensions {
+                width: 1280,
+                height: 960
+            })
+        );
+        assert!(matches!(
+            telegram.receive_message().await,
+            Ok(Message::Empty)
+        ));
+
+        telegram
+            .send_message("42".to_string(), "Готово".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            sent.lock().unwrap()[0],
+            json!({"chat_id": "42", "text": "Готово"})
+        );
+        let blocked = telegram
+            .send_message("13".to_string(), "Готово".to_string())
+            .await;
+        assert!(matches!(blocked, Err(Error::ChatUnreachable(_))));
+        assert_eq!(
+            telegram.instance_state().await.unwrap(),
+            InstanceState::Authorized
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+
+/// Ответ Telegram Bot API, при `ok: false` результата нет
+#[derive(Debug, Deserialize)]
+pub struct Response<T> {
+    pub ok: bool,
+    pub result: Option<T>,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Update {
+    pub update_id: i64,
+    /// Отсутствует у обновлений других типов, например правок сообщений
+    pub message: Option<IncomingMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IncomingMessage {
+    pub message_id: i64,
+    pub chat: Chat,
+    pub from: Option<User>,
+    pub text: Option<String>,
+    pub photo: Option<Vec<PhotoSize>>,
+    pub document: Option<Document>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Chat {
+    pub id: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct User {
+    pub first_name: String,
+    pub last_name: Option<String>,
+}
+
+/// Один из размеров фото, Telegram присылает несколько по возрастанию
+#[derive(Debug, Deserialize)]
+pub struct PhotoSize {
+    pub file_id: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Фото, отправленное файлом без сжатия
+#[derive(Debug, Deserialize)]
+pub struct Document {
+    pub file_id: String,
+    pub mime_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct File {
+    pub file_path: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SendMessage {
+    pub chat_id: String,
+    pub text: String,
+}
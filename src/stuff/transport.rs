@@ -1,32 +1,67 @@
 use crate::config::config;
-use crate::stuff::data_types::{Message, OrderMessage, OrderState, ReceivedMessage};
+use crate::stuff::data_types::{
+    DeliveryStatus, InstanceState, Message, MessageStatus, OrderFile, ReceivedImage,
+    ReceivedMessage,
+};
 use crate::stuff::error::{Error, Result};
-use crate::stuff::wa_types::{RootMsg, SendMessage};
+use crate::stuff::image_info::thumbnail_dimensions;
+use crate::stuff::metrics::metrics;
+use crate::stuff::wa_types::{
+    IncomingMessage, Notification, SendMessage, SendMessageResponse, StateInstanceChanged,
+    Webhook,
+};
+use hmac_sha256::Hash;
 use log::{debug, error};
 use reqwest::StatusCode;
+use std::fs;
+use std::path::{Path, PathBuf};
 pub trait Transport {
     async fn receive_message(&self) -> Result<Message>;
     async fn send_message(&self, chat_id: String, msg: String) -> Result<()>;
-
-    async fn send_order(&self, order: OrderState) -> Result<String>;
+    /// Как `send_message`, но возвращает идентификатор отправленного сообщения,
+    /// если мессенджер его сообщает
+    async fn send_message_id(&self, chat_id: String, msg: String) -> Result<Option<String>> {
+        self.send_message(chat_id, msg).await.map(|_| None)
+    }
+    /// Текущее состояние подключения к мессенджеру
+    async fn instance_state(&self) -> Result<InstanceState>;
+    /// Состояние каждого канала по имени, у транспорта с одним каналом имя пустое
+    async fn channel_states(&self) -> Vec<(String, Result<InstanceState>)> {
+        vec![(String::new(), self.instance_state().await)]
+    }
 }
 
 pub struct WhatsApp {
+    client: reqwest::Client,
     api_url: String,
     token: String,
-    admin_chat_id: String,
+    /// Чат администратора, если он обслуживается этим транспортом
+    admin_chat_id: Option<String>,
     timeout_seconds: u16,
-    worker_url: String,
 }
 
 impl WhatsApp {
-    pub fn new() -> Self {
+    pub fn new(client: reqwest::Client) -> Self {
+        WhatsApp::with_instance(
+            client,
+            &config().ID_INSTANCE,
+            &config().API_TOKEN_INSTANCE,
+            Some(config().ADMIN_CHAT_ID.to_owned()),
+        )
+    }
+
+    pub fn with_instance(
+        client: reqwest::Client,
+        id_instance: &str,
+        token: &str,
+        admin_chat_id: Option<String>,
+    ) -> Self {
         Self {
-            api_url: format!("{}/waInstance{}", &config().API_URL, &config().ID_INSTANCE),
-            token: config().API_TOKEN_INSTANCE.to_owned(),
-            admin_chat_id: config().ADMIN_CHAT_ID.to_owned(),
+            client,
+            api_url: format!("{}/waInstance{}", &config().API_URL, id_instance),
+            token: token.to_owned(),
+            admin_chat_id,
             timeout_seconds: 5,
-            worker_url: config().WORKER_URL.to_owned(),
         }
     }
 
@@ -36,66 +71,204 @@ impl WhatsApp {
             self.api_url, self.token, receipt_id
         );
 
-        let response = reqwest::Client::new().delete(&url).send().await;
+        let response = metrics()
+            .timed("green_api", "deleteNotification", self.client.delete(&url))
+            .await;
         if let Err(e) = response {
             error!("[delete_notification] {:?}", e);
             self.log_to_admin(e.to_string()).await;
         }
     }
 
+    /// Откладывает уведомление, которое не удалось разобрать, и сообщает администратору
+    async fn quarantine(&self, receipt_id: Option<i64>, raw: &str, error: &Error) {
+        let name = match receipt_id {
+            Some(id) => id.to_string(),
+            // Без номера уведомление не удалить и Green API вернет его снова,
+            // имя по содержимому не дает сохранять и сообщать о нем повторно
+            None => format!("unknown-{}", hex::encode(&Hash::hash(raw.as_bytes())[..8])),
+        };
+        let path = Path::new(&config().QUARANTINE_DIR).join(format!("{}.json", name));
+        if receipt_id.is_none() && path.exists() {
+            debug!("[quarantine] notification {} already saved", name);
+            return;
+        }
+        error!("[quarantine] notification {}: {}", name, error);
+        let saved = match write_quarantine(&config().QUARANTINE_DIR, &name, raw) {
+            Ok(path) => path.display().to_string(),
+            Err(e) => {
+                error!("[quarantine] {}", e);
+                "не сохранено".to_string()
+            }
+        };
+        self.log_to_admin(format!(
+            "Уведомление Green API {} не разобрано: {} ({})",
+            name, error, saved
+        ))
+        .await;
+    }
+
     pub async fn log_to_admin(&self, msg: String) {
-        let res = self.send_message(self.admin_chat_id.clone(), msg).await;
+        let Some(admin_chat_id) = self.admin_chat_id.clone() else {
+            return;
+        };
+        let res = self.send_message(admin_chat_id, msg).await;
         if let Err(e) = res {
             error!("[log_to_admin] {:?}", e);
         }
     }
 }
 
+/// Разбирает тело уведомления Green API в сообщение для обработчика
+fn parse_webhook(body: serde_json::Value) -> Result<Message> {
+    let webhook = serde_json::from_value::<Webhook>(body)
+        .map_err(|e| Error::MalformedNotification(e.to_string()))?;
+    let message = match webhook {
+        Webhook::IncomingMessageReceived(m) => parse_incoming(*m)?,
+        Webhook::OutgoingMessageStatus(s) => Message::Status(MessageStatus {
+            chat_id: s.chat_id,
+            message_id: s.id_message,
+            status: match s.status.as_str() {
+                "sent" => DeliveryStatus::Sent,
+                "delivered" => DeliveryStatus::Delivered,
+                "read" => DeliveryStatus::Read,
+                "failed" => DeliveryStatus::Failed,
+                "noAccount" => DeliveryStatus::NoAccount,
+                _ => DeliveryStatus::Other,
+            },
+        }),
+        Webhook::StateInstanceChanged(s) => {
+            Message::InstanceState(String::new(), parse_state(&s.state_instance))
+        }
+        Webhook::IncomingCall(c) => Message::Call(c.from),
+        Webhook::Unsupported => Message::Empty,
+    };
+    Ok(message)
+}
+
+fn parse_state(state: &str) -> InstanceState {
+    match state {
+        "authorized" => InstanceState::Authorized,
+        "notAuthorized" => InstanceState::NotAuthorized,
+        "blocked" => InstanceState::Blocked,
+        "sleepMode" => InstanceState::SleepMode,
+        "starting" => InstanceState::Starting,
+        "yellowCard" => InstanceState::YellowCard,
+        _ => InstanceState::Other,
+    }
+}
+
+fn parse_incoming(m: IncomingMessage) -> Result<Message> {
+    let message_id = Some(m.id_message).filter(|id| !id.is_empty());
+    let chat_id = m.sender_data.chat_id;
+    let customer_name = m.sender_data.sender_name;
+    let data = m.message_data;
+    let malformed = |what: &str| {
+        Error::MalformedNotification(format!("{} without {}", data.type_message, what))
+    };
+    let message = match data.type_message.as_ref() {
+        "imageMessage" => {
+            let file = data
+                .file_message_data
+                .as_ref()
+                .ok_or_else(|| malformed("fileMessageData"))?;
+            Message::Image(ReceivedImage {
+                chat_id,
+                customer_name,
+                message_id,
+                file: OrderFile {
+                    dimensions: thumbnail_dimensions(&file.jpeg_thumbnail),
+                    url: file.download_url.clone(),
+                },
+            })
+        }
+        "textMessage" | "extendedTextMessage" => {
+            let text = data
+                .text_message_data
+                .as_ref()
+                .map(|t| t.text_message.clone())
+                .or(data
+                    .extended_text_message_data
+                    .as_ref()
+                    .map(|t| t.text.clone()))
+                .ok_or_else(|| malformed("text"))?;
+            Message::Text(ReceivedMessage {
+                chat_id,
+                customer_name,
+                message_id,
+                message: text,
+            })
+        }
+        _ => Message::Empty,
+    };
+    Ok(message)
+}
+
+/// Номер уведомления из тела, которое не разбирается целиком
+fn receipt_id(raw: &str) -> Option<i64> {
+    if let Ok(value) = serde_json::from_str::<serde_json::Value>(raw) {
+        let id = &value["receiptId"];
+        return id.as_i64().or_else(|| id.as_str()?.parse().ok());
+    }
+    // Тело обрезано или это не JSON: поле ищется в тексте
+    const FIELD: &str = "\"receiptId\"";
+    let rest = &raw[raw.find(FIELD)? + FIELD.len()..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let rest = rest.strip_prefix('"').unwrap_or(rest);
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+/// Сохраняет неразобранное уведомление для последующего анализа
+fn write_quarantine(dir: &str, name: &str, raw: &str) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = Path::new(dir).join(format!("{}.json", name));
+    fs::write(&path, raw)?;
+    Ok(path)
+}
+
 impl Transport for WhatsApp {
     async fn receive_message(&self) -> Result<Message> {
         let url = format!(
             "{}/receiveNotification/{}?receiveTimeout={}",
             self.api_url, self.token, self.timeout_seconds
         );
-        let payload = reqwest::Client::new().get(&url).send().await?;
+        let payload = metrics()
+            .timed("green_api", "receiveNotification", self.client.get(&url))
+            .await?;
 
         match payload.status() {
             StatusCode::OK => {
-                let msg_result = payload.json::<RootMsg>().await;
-                match msg_result {
-                    Ok(mut m) => {
-                        self.delete_notification(m.receipt_id).await;
-                        match m.body.message_data.type_message.as_ref() {
-                            "imageMessage" => Ok(Message::Image(ReceivedMessage {
-                                chat_id: m.body.sender_data.chat_id,
-                                customer_name: m.body.sender_data.sender_name,
-                                message: m
-                                    .body
-                                    .message_data
-                                    .file_message_data
-                                    .take()
-                                    .unwrap()
-                                    .download_url,
-                            })),
-                            "textMessage" => Ok(Message::Text(ReceivedMessage {
-                                chat_id: m.body.sender_data.chat_id,
-                                customer_name: m.body.sender_data.sender_name,
-                                message: m
-                                    .body
-                                    .message_data
-                                    .text_message_data
-                                    .take()
-                                    .unwrap()
-                                    .text_message,
-                            })),
-                            _ => Ok(Message::Empty),
+                let text = payload.text().await?;
+                // Номер нужен для удаления из очереди, даже если тело не разбирается
+                let receipt_id = receipt_id(&text);
+                let notification = match serde_json::from_str::<Option<Notification>>(&text) {
+                    Ok(Some(notification)) => notification,
+                    Ok(None) => {
+                        debug!("Новых сообщений нет");
+                        return Ok(Message::Empty);
+                    }
+                    Err(e) => {
+                        let error = Error::MalformedNotification(e.to_string());
+                        self.quarantine(receipt_id, &text, &error).await;
+                        if let Some(receipt_id) = receipt_id {
+                            self.delete_notification(receipt_id).await;
                         }
+                        return Ok(Message::Empty);
                     }
-                    Err(_) => {
-                        debug!("Новых сообщений нет");
-                        Ok(Message::Empty)
+                };
+                let message = match parse_webhook(notification.body) {
+                    Ok(message) => message,
+                    Err(error) => {
+                        self.quarantine(Some(notification.receipt_id), &text, &error)
+                            .await;
+                        Message::Empty
                     }
-                }
+                };
+                self.delete_notification(notification.receipt_id).await;
+                Ok(message)
             }
             _ => Err(Error::FailedToGetNewMessage(
                 payload.status(),
@@ -105,53 +278,60 @@ impl Transport for WhatsApp {
     }
 
     async fn send_message(&self, chat_id: String, message: String) -> Result<()> {
+        self.send_message_id(chat_id, message).await.map(|_| ())
+    }
+
+    async fn send_message_id(&self, chat_id: String, message: String) -> Result<Option<String>> {
         let url = format!("{}/sendMessage/{}", &self.api_url, &self.token);
         let msg = SendMessage { chat_id, message };
 
-        reqwest::Client::new()
-            .post(&url)
-            .json::<SendMessage>(&msg)
-            .send()
+        let request = self.client.post(&url).json::<SendMessage>(&msg);
+        let response = metrics()
+            .timed("green_api", "sendMessage", request)
             .await?;
-        Ok(())
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            error!("[send_message] {} {}", status, body);
+            return Err(Error::from_api_status(status, body));
+        }
+        match serde_json::from_str::<SendMessageResponse>(&body) {
+            Ok(sent) => {
+                debug!("Message {} sent", sent.id_message);
+                Ok(Some(sent.id_message))
+            }
+            Err(_) => Err(Error::ApiFailed(status, body)),
+        }
     }
 
-    async fn send_order(&self, order: OrderState) -> Result<String> {
-        let send_result = reqwest::Client::new()
-            .post(&self.worker_url)
-            .json::<OrderMessage>(&order.clone().into())
-            .send()
-            .await;
-        match send_result {
-            Ok(response) => {
-                let status = response.status();
-                let text = response.text().await?;
-                if status == StatusCode::CREATED {
-                    Ok(text)
-                } else {
-                    error!("[send_order] {:?}", text);
-                    self.log_to_admin(text.clone()).await;
-                    Err(Error::OrderFailed(text))
-                }
-            }
-            Err(e) => {
-                let msg = format!("Failed to send order to worker! Error: {}", e);
-                error!("{msg}");
-                self.log_to_admin(msg).await;
-                Err(Error::Request(e))
-            }
+    async fn instance_state(&self) -> Result<InstanceState> {
+        let url = format!("{}/getStateInstance/{}", self.api_url, self.token);
+        let response = metrics()
+            .timed("green_api", "getStateInstance", self.client.get(&url))
+            .await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(Error::from_api_status(status, body));
+        }
+        match serde_json::from_str::<StateInstanceChanged>(&body) {
+            Ok(state) => Ok(parse_state(&state.state_instance)),
+            Err(_) => Err(Error::ApiFailed(status, body)),
         }
     }
 }
 
+#[cfg(test)]
 #[derive(Default)]
 pub struct MockTransport;
 
+#[cfg(test)]
 impl Transport for MockTransport {
     async fn receive_message(&self) -> Result<Message> {
         Ok(Message::Text(ReceivedMessage {
             chat_id: "79146796712@c.us".to_string(),
             customer_name: "Andrey".to_string(),
+            message_id: None,
             message: "hi".to_string(),
         }))
     }
@@ -161,8 +341,93 @@ impl Transport for MockTransport {
         Ok(())
     }
 
-    async fn send_order(&self, order: OrderState) -> Result<String> {
-        println!("Sending order to: {:?}", &order);
-        Ok("".to_string())
+    async fn instance_state(&self) -> Result<InstanceState> {
+        Ok(InstanceState::Authorized)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn incoming(message_data: serde_json::Value) -> serde_json::Value {
+        json!({
+            "typeWebhook": "incomingMessageReceived",
+            "instanceData": {"idInstance": 1, "wid": "79140000000@c.us", "typeInstance": "whatsapp"},
+            "timestamp": 1760000000,
+            "idMessage": "BAE5",
+            "senderData": {
+                "chatId": "79146795555@c.us",
+                "chatName": "Andrey",
+                "sender": "79146795555@c.us",
+                "senderName": "Andrey",
+                "senderContactName": ""
+            },
+            "messageData": message_data
+        })
+    }
+
+    #[test]
+    fn parse_incoming_test() {
+        let text = incoming(json!({
+            "typeMessage": "extendedTextMessage",
+            "extendedTextMessageData": {"text": "Готово"}
+        }));
+        assert!(matches!(parse_webhook(text), Ok(Message::Text(m)) if m.message == "Готово"));
+        let broken = incoming(json!({"typeMessage": "imageMessage"}));
+        assert!(matches!(
+            parse_webhook(broken),
+            Err(Error::MalformedNotification(_))
+        ));
+        // Без необязательных полей превью и пересылки
+        let image = incoming(json!({
+            "typeMessage": "imageMessage",
+            "fileMessageData": {"downloadUrl": "http://localhost/1.jpg"}
+        }));
+        assert!(matches!(parse_webhook(image), Ok(Message::Image(_))));
+        assert!(parse_webhook(json!({"typeWebhook": "incomingMessageReceived"})).is_err());
+    }
+
+    #[test]
+    fn parse_status_test() {
+        let status = json!({
+            "typeWebhook": "outgoingMessageStatus",
+            "chatId": "79146795555@c.us",
+            "idMessage": "BAE5",
+            "status": "noAccount",
+            "timestamp": 1760000000
+        });
+        assert!(matches!(
+            parse_webhook(status),
+            Ok(Message::Status(s)) if s.status == DeliveryStatus::NoAccount
+        ));
+        let state = json!({"typeWebhook": "stateInstanceChanged", "stateInstance": "yellowCard"});
+        assert!(matches!(
+            parse_webhook(state),
+            Ok(Message::InstanceState(_, InstanceState::YellowCard))
+        ));
+        let call =
+            json!({"typeWebhook": "incomingCall", "from": "79146795555@c.us", "status": "offer"});
+        assert!(matches!(parse_webhook(call), Ok(Message::Call(_))));
+        let outgoing = json!({"typeWebhook": "outgoingMessageReceived", "idMessage": "1"});
+        assert!(matches!(parse_webhook(outgoing), Ok(Message::Empty)));
+    }
+
+    #[test]
+    fn receipt_id_test() {
+        assert_eq!(receipt_id(r#"{"receiptId":42,"body":{"x":1}}"#), Some(42));
+        assert_eq!(receipt_id(r#"{"receiptId":"42","body":null}"#), Some(42));
+        assert_eq!(receipt_id(r#"{"receiptId": 42, "body": {"typeWebhook"#), Some(42));
+        assert_eq!(receipt_id(r#"{"body":{}}"#), None);
+        assert_eq!(receipt_id("<html>502</html>"), None);
+    }
+
+    #[test]
+    fn write_quarantine_test() {
+        let dir = std::env::temp_dir().join("astrafoto-quarantine-test");
+        let path = write_quarantine(dir.to_str().unwrap(), "42", "{\"receiptId\":42}").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\"receiptId\":42}");
+        fs::remove_dir_all(dir).unwrap();
     }
 }
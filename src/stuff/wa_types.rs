@@ -6,46 +6,77 @@ pub struct TextMessageData {
     pub text_message: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExtendedTextMessageData {
+    pub text: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MessageData {
     #[serde(rename = "typeMessage")]
     pub type_message: String,
     #[serde(rename = "textMessageData")]
     pub text_message_data: Option<TextMessageData>,
+    #[serde(rename = "extendedTextMessageData")]
+    pub extended_text_message_data: Option<ExtendedTextMessageData>,
     #[serde(rename = "fileMessageData")]
     pub file_message_data: Option<FileMessageData>,
 }
 
+// Обязательны только поля, без которых сообщение не обработать,
+// остальные Green API присылает не всегда.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SenderData {
     #[serde(rename = "chatId")]
     pub chat_id: String,
-    #[serde(rename = "chatName")]
+    #[serde(rename = "chatName", default)]
     pub chat_name: String,
+    #[serde(default)]
     pub sender: String,
-    #[serde(rename = "senderName")]
+    #[serde(rename = "senderName", default)]
     pub sender_name: String,
-    #[serde(rename = "senderContactName")]
+    #[serde(rename = "senderContactName", default)]
     pub sender_contact_name: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InstanceData {
-    #[serde(rename = "idInstance")]
+    #[serde(rename = "idInstance", default)]
     pub id_instance: i64,
+    #[serde(default)]
     pub wid: String,
-    #[serde(rename = "typeInstance")]
+    #[serde(rename = "typeInstance", default)]
     pub type_instance: String,
 }
 
+/// Уведомление из очереди Green API. Тело разбирается отдельно,
+/// чтобы квитанцию можно было удалить даже для неизвестного типа.
+#[derive(Debug, Deserialize)]
+pub struct Notification {
+    #[serde(rename = "receiptId")]
+    pub receipt_id: i64,
+    pub body: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "typeWebhook", rename_all = "camelCase")]
+pub enum Webhook {
+    IncomingMessageReceived(Box<IncomingMessage>),
+    OutgoingMessageStatus(OutgoingMessageStatus),
+    StateInstanceChanged(StateInstanceChanged),
+    IncomingCall(IncomingCall),
+    /// Исходящие сообщения с телефона и прочие уведомления, которые бот не обрабатывает
+    #[serde(other)]
+    Unsupported,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
-pub struct Body {
-    #[serde(rename = "typeWebhook")]
-    pub type_webhook: String,
+pub struct IncomingMessage {
     #[serde(rename = "instanceData")]
-    pub instance_data: InstanceData,
+    pub instance_data: Option<InstanceData>,
+    #[serde(default)]
     pub timestamp: i64,
-    #[serde(rename = "idMessage")]
+    #[serde(rename = "idMessage", default)]
     pub id_message: String,
     #[serde(rename = "senderData")]
     pub sender_data: SenderData,
@@ -53,29 +84,44 @@ pub struct Body {
     pub message_data: MessageData,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RootMsg {
-    #[serde(rename = "receiptId")]
-    pub receipt_id: i64,
-    pub body: Body,
+#[derive(Debug, Deserialize)]
+pub struct OutgoingMessageStatus {
+    #[serde(rename = "chatId")]
+    pub chat_id: String,
+    #[serde(rename = "idMessage")]
+    pub id_message: String,
+    pub status: String,
+}
+
+/// Также ответ метода getStateInstance
+#[derive(Debug, Deserialize)]
+pub struct StateInstanceChanged {
+    #[serde(rename = "stateInstance")]
+    pub state_instance: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IncomingCall {
+    pub from: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FileMessageData {
     #[serde(rename = "downloadUrl")]
     pub download_url: String,
+    #[serde(default)]
     pub caption: String,
-    #[serde(rename = "fileName")]
+    #[serde(rename = "fileName", default)]
     pub file_name: String,
-    #[serde(rename = "jpegThumbnail")]
+    #[serde(rename = "jpegThumbnail", default)]
     pub jpeg_thumbnail: String,
-    #[serde(rename = "isAnimated")]
+    #[serde(rename = "isAnimated", default)]
     pub is_animated: bool,
-    #[serde(rename = "mimeType")]
+    #[serde(rename = "mimeType", default)]
     pub mime_type: String,
-    #[serde(rename = "forwardingScore")]
+    #[serde(rename = "forwardingScore", default)]
     pub forwarding_score: i64,
-    #[serde(rename = "isForwarded")]
+    #[serde(rename = "isForwarded", default)]
     pub is_forwarded: bool,
 }
 
@@ -85,3 +131,9 @@ pub struct SendMessage {
     pub chat_id: String,
     pub message: String,
 }
+
+#[derive(Debug, Deserialize)]
+pub struct SendMessageResponse {
+    #[serde(rename = "idMessage")]
+    pub id_message: String,
+}
@@ -0,0 +1,61 @@
+use crate::config::config;
+use crate::stuff::data_types::{Order, OrderMessage};
+use crate::stuff::error::{Error, Result};
+use crate::stuff::metrics::metrics;
+use crate::stuff::sink::{OrderSink, Submitted};
+use crate::stuff::worker_api::{ApiVersion, OrderRequestV2, parse_response};
+use crate::stuff::worker_auth::WorkerAuth;
+use chrono::Utc;
+use reqwest::header::CONTENT_TYPE;
+
+/// Сервис печати, которому передаются готовые заказы
+pub struct Worker {
+    client: reqwest::Client,
+    url: String,
+    version: ApiVersion,
+    auth: WorkerAuth,
+}
+
+impl Worker {
+    pub fn new(client: reqwest::Client) -> Result<Self> {
+        Ok(Self {
+            client,
+            url: config().WORKER_URL.to_owned(),
+            version: ApiVersion::parse(config().WORKER_API_VERSION)?,
+            auth: WorkerAuth::from_config()?,
+        })
+    }
+}
+
+impl OrderSink for Worker {
+    /// Номер заказа и время готовности выдает сервис
+    async fn submit(&self, order: Order) -> Result<Submitted> {
+        let trace_id = order.trace_id.clone();
+        // Подписывается ровно то тело, которое уходит в запросе
+        let body = match self.version {
+            ApiVersion::V1 => {
+                serde_json::to_vec(&OrderMessage::try_from(order)?.with_public_urls())
+            }
+            ApiVersion::V2 => serde_json::to_vec(&OrderRequestV2::try_from(order)?),
+        }
+        .map_err(|e| Error::OrderFailed(e.to_string()))?;
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header("X-Correlation-Id", trace_id);
+        for (name, value) in self.auth.headers(&body, Utc::now()) {
+            request = request.header(name, value);
+        }
+        let response = metrics()
+            .timed("worker", "submit", request.body(body))
+            .await?;
+        let status = response.status();
+        let body = response.text().await?;
+        let created = parse_response(self.version, status, &body)?;
+        Ok(Submitted {
+            order_id: created.order_id,
+            ready_at: created.estimated_ready_at,
+        })
+    }
+}
@@ -0,0 +1,195 @@
+use crate::stuff::data_types::{Fit, Order, OrderMessage, OrderOption};
+use crate::stuff::error::{Error, Result};
+use crate::stuff::files::public_url;
+use crate::stuff::money::Money;
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+/// Версия API сервиса печати.
+/// v1 - исходный формат: тело [`OrderMessage`], в ответ 201 и номер заказа текстом.
+/// Поле `chat_id` добавлено к исходному формату для callback.
+/// v2 - [`OrderRequestV2`] с полем `version`, в ответ [`OrderCreated`].
+/// Ошибки проверки заказа в обеих версиях приходят как 400/422 с [`ValidationErrors`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+
+impl ApiVersion {
+    pub fn parse(version: u8) -> Result<ApiVersion> {
+        match version {
+            1 => Ok(ApiVersion::V1),
+            2 => Ok(ApiVersion::V2),
+            _ => Err(Error::OrderFailed(format!(
+                "unsupported worker API version {}",
+                version
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrderRequestV2 {
+    pub version: u8,
+    pub customer: CustomerV2,
+    pub product: String,
+    pub options: Vec<OrderOption>,
+    pub paper_type: String,
+    pub paper_size: String,
+    pub fit: Option<Fit>,
+    pub price: Money,
+    pub total: Money,
+    pub payment: Option<PaymentV2>,
+    /// Оценка готовности бота, сервис может вернуть свою
+    pub ready_at: Option<DateTime<Utc>>,
+    pub files: Vec<FileV2>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CustomerV2 {
+    pub chat_id: String,
+    pub name: String,
+    pub phone: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PaymentV2 {
+    pub id: String,
+    pub paid: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileV2 {
+    pub url: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl TryFrom<Order> for OrderRequestV2 {
+    type Error = Error;
+
+    fn try_from(order: Order) -> Result<Self> {
+        let files = order
+            .files
+            .iter()
+            .map(|f| FileV2 {
+                url: public_url(&f.url),
+                width: f.dimensions.map(|d| d.width),
+                height: f.dimensions.map(|d| d.height),
+            })
+            .collect();
+        let message = OrderMessage::try_from(order)?;
+        Ok(Self {
+            version: 2,
+            customer: CustomerV2 {
+                chat_id: message.chat_id,
+                name: message.name,
+                phone: message.phone,
+            },
+            product: message.product,
+            options: message.options,
+            paper_type: message.paper_type,
+            paper_size: message.paper_size,
+            fit: message.fit,
+            price: message.price,
+            total: message.total,
+            payment: message.payment_id.map(|id| PaymentV2 {
+                id,
+                paid: message.paid,
+            }),
+            ready_at: message.ready_at,
+            files,
+        })
+    }
+}
+
+/// Ответ v2 на принятый заказ
+#[derive(Debug, Deserialize, Eq, PartialEq)]
+pub struct OrderCreated {
+    pub order_id: String,
+    pub estimated_ready_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+}
+
+/// Ошибка в поле заказа, `message` показывается клиенту
+#[derive(Debug, Clone, Deserialize, Eq, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Callback сервиса печати о ходе заказа. `chat_id` - чат, из которого
+/// пришел заказ: `chat_id` заказа v1 или `customer.chat_id` v2
+#[derive(Debug, Clone, Deserialize)]
+pub struct WorkerEvent {
+    pub order_id: String,
+    pub chat_id: String,
+    pub status: WorkerStatus,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WorkerStatus {
+    Printing,
+    Ready,
+}
+
+/// Разбирает ответ сервиса на отправленный заказ
+pub fn parse_response(version: ApiVersion, status: StatusCode, body: &str) -> Result<OrderCreated> {
+    match (version, status) {
+        (ApiVersion::V1, StatusCode::CREATED) => Ok(OrderCreated {
+            order_id: body.trim().to_string(),
+            estimated_ready_at: None,
+        }),
+        (ApiVersion::V2, StatusCode::OK | StatusCode::CREATED) => {
+            serde_json::from_str::<OrderCreated>(body)
+                .map_err(|e| Error::OrderFailed(format!("invalid response {}: {}", e, body)))
+        }
+        (_, StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY) => {
+            match serde_json::from_str::<ValidationErrors>(body) {
+                Ok(v) if !v.errors.is_empty() => Err(Error::OrderRejected(v.errors)),
+                _ => Err(Error::OrderRefused(format!("{} {}", status, body))),
+            }
+        }
+        // Запрос с тем же телом снова получит отказ, кроме таймаута и лимита запросов
+        (_, StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS) => {
+            Err(Error::OrderFailed(format!("{} {}", status, body)))
+        }
+        (_, status) if status.is_client_error() => {
+            Err(Error::OrderRefused(format!("{} {}", status, body)))
+        }
+        _ => Err(Error::OrderFailed(format!("{} {}", status, body))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_response_test() {
+        let created = parse_response(ApiVersion::V1, StatusCode::CREATED, "A-15\n").unwrap();
+        assert_eq!(created.order_id, "A-15");
+        assert!(parse_response(ApiVersion::V1, StatusCode::OK, "A-15").is_err());
+
+        let body = r#"{"order_id": "B-7", "estimated_ready_at": "2026-10-20T01:00:00Z"}"#;
+        let created = parse_response(ApiVersion::V2, StatusCode::CREATED, body).unwrap();
+        assert_eq!(created.order_id, "B-7");
+        assert!(created.estimated_ready_at.is_some());
+        assert!(parse_response(ApiVersion::V2, StatusCode::CREATED, "B-7").is_err());
+
+        let body = r#"{"errors": [{"field": "paper_size", "message": "Формат 30x40 недоступен"}]}"#;
+        let rejected = parse_response(ApiVersion::V2, StatusCode::UNPROCESSABLE_ENTITY, body);
+        assert!(matches!(rejected, Err(Error::OrderRejected(e)) if e[0].field == "paper_size"));
+        let failed = parse_response(ApiVersion::V1, StatusCode::BAD_REQUEST, "bad order");
+        assert!(matches!(failed, Err(Error::OrderRefused(_))));
+        let failed = parse_response(ApiVersion::V1, StatusCode::SERVICE_UNAVAILABLE, "");
+        assert!(matches!(failed, Err(Error::OrderFailed(_))));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::config::config;
+use crate::stuff::error::{Error, Result};
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use hmac_sha256::HMAC;
+
+pub const TIMESTAMP_HEADER: &str = "X-Timestamp";
+pub const SIGNATURE_HEADER: &str = "X-Signature";
+
+/// Допустимое расхождение часов бота и сервиса печати, секунды
+const MAX_CLOCK_SKEW: i64 = 5 * 60;
+
+/// Подлинность запросов между ботом и сервисом печати: заказы подписываются
+/// при отправке, callback сервиса проверяются тем же секретом.
+#[derive(Debug, Clone)]
+pub enum WorkerAuth {
+    None,
+    /// Заголовок `Authorization: Bearer <секрет>`
+    Bearer(String),
+    /// HMAC-SHA256 от "{timestamp}.{тело}" в X-Signature, время unix в X-Timestamp
+    Hmac(String),
+}
+
+impl WorkerAuth {
+    pub fn from_config() -> Result<Self> {
+        let secret = config().WORKER_SECRET.to_owned();
+        let auth = config().WORKER_AUTH.as_str();
+        if auth != "none" && secret.is_empty() {
+            return Err(Error::WorkerAuthInvalid(
+                "WORKER_SECRET is empty".to_string(),
+            ));
+        }
+        match auth {
+            "none" => Ok(WorkerAuth::None),
+            "bearer" => Ok(WorkerAuth::Bearer(secret)),
+            "hmac" => Ok(WorkerAuth::Hmac(secret)),
+            other => Err(Error::WorkerAuthInvalid(other.to_string())),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !matches!(self, WorkerAuth::None)
+    }
+
+    /// Заголовки запроса с телом `body`
+    pub fn headers(&self, body: &[u8], now: DateTime<Utc>) -> Vec<(&'static str, String)> {
+        match self {
+            WorkerAuth::None => vec![],
+            WorkerAuth::Bearer(token) => vec![("Authorization", format!("Bearer {}", token))],
+            WorkerAuth::Hmac(secret) => {
+                let timestamp = now.timestamp().to_string();
+                let signature = sign(secret, &timestamp, body);
+                vec![(TIMESTAMP_HEADER, timestamp), (SIGNATURE_HEADER, signature)]
+            }
+        }
+    }
+
+    /// Проверка входящего запроса. Без секрета callback не принимаются вовсе
+    pub fn verify(&self, headers: &HeaderMap, body: &[u8], now: DateTime<Utc>) -> Result<()> {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| Error::SignatureInvalid(format!("{} missing", name)))
+        };
+        match self {
+            WorkerAuth::None => Err(Error::SignatureInvalid(
+                "worker auth is not configured".to_string(),
+            )),
+            WorkerAuth::Bearer(token) => {
+                let sent = header("Authorization")?.strip_prefix("Bearer ");
+                match sent {
+                    Some(sent) if constant_eq(sent.as_bytes(), token.as_bytes()) => Ok(()),
+                    _ => Err(Error::SignatureInvalid("wrong token".to_string())),
+                }
+            }
+            WorkerAuth::Hmac(secret) => {
+                let timestamp = header(TIMESTAMP_HEADER)?;
+                let sent_at = timestamp
+                    .parse::<i64>()
+                    .map_err(|_| Error::SignatureInvalid(format!("timestamp {}", timestamp)))?;
+                if (now.timestamp() - sent_at).abs() > MAX_CLOCK_SKEW {
+                    return Err(Error::SignatureInvalid(format!(
+                        "timestamp {} expired",
+                        timestamp
+                    )));
+                }
+                let signature = header(SIGNATURE_HEADER)?;
+                let expected = signature
+                    .strip_prefix("sha256=")
+                    .and_then(|s| hex::decode(s).ok())
+                    .and_then(|s| <[u8; 32]>::try_from(s).ok())
+                    .ok_or_else(|| Error::SignatureInvalid(format!("signature {}", signature)))?;
+                if !HMAC::verify(signed_payload(timestamp, body), secret, &expected) {
+                    return Err(Error::SignatureInvalid("wrong signature".to_string()));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Подпись в формате "sha256=<hex>"
+pub fn sign(secret: &str, timestamp: &str, body: &[u8]) -> String {
+    let mac = HMAC::mac(signed_payload(timestamp, body), secret);
+    format!("sha256={}", hex::encode(mac))
+}
+
+fn signed_payload(timestamp: &str, body: &[u8]) -> Vec<u8> {
+    [timestamp.as_bytes(), b".", body].concat()
+}
+
+/// Сравнение без раннего выхода, чтобы время ответа не выдавало токен
+fn constant_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::http::HeaderValue;
+    use chrono::TimeDelta;
+
+    fn to_headers(pairs: Vec<(&'static str, String)>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(name, HeaderValue::from_str(&value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn hmac_test() {
+        let auth = WorkerAuth::Hmac("secret".to_string());
+        let now = Utc::now();
+        let body = br#"{"order_id":"A-15"}"#;
+        let headers = to_headers(auth.headers(body, now));
+        assert!(auth.verify(&headers, body, now).is_ok());
+        assert!(
+            auth.verify(&headers, br#"{"order_id":"A-16"}"#, now)
+                .is_err()
+        );
+        let late = now + TimeDelta::minutes(10);
+        assert!(auth.verify(&headers, body, late).is_err());
+        let other = WorkerAuth::Hmac("other".to_string());
+        assert!(other.verify(&headers, body, now).is_err());
+        assert!(auth.verify(&HeaderMap::new(), body, now).is_err());
+    }
+
+    #[test]
+    fn bearer_test() {
+        let auth = WorkerAuth::Bearer("token".to_string());
+        let now = Utc::now();
+        let headers = to_headers(auth.headers(b"", now));
+        assert!(auth.verify(&headers, b"", now).is_ok());
+        let wrong = to_headers(vec![("Authorization", "Bearer tokem".to_string())]);
+        assert!(auth.verify(&wrong, b"", now).is_err());
+        assert!(WorkerAuth::None.verify(&headers, b"", now).is_err());
+    }
+}